@@ -0,0 +1,110 @@
+/*!
+Splitting per-pixel work into bands of whole rows.
+
+With the `threads` feature (which needs `std`, so it's for native builds
+only), each band gets a thread of its own. Without it, there's just the one
+band, the whole image, done right here. Either way every pixel gets exactly
+the same arithmetic, so the images are identical. (That's why subdivision
+and boundary tracing, which decide what to iterate by looking at the whole
+image, don't get split into bands.)
+*/
+
+use core::ops::Range;
+
+/**
+One or more per-pixel buffers that can be cut into bands together. Each
+element of a buffer is a pixel.
+*/
+pub trait Rows: Sized + Send {
+    /// Split into the first `n` pixels and the rest.
+    fn split_at(self, n: usize) -> (Self, Self);
+}
+
+impl<T: Send> Rows for &mut [T] {
+    fn split_at(self, n: usize) -> (Self, Self) { self.split_at_mut(n) }
+}
+
+impl<A: Rows, B: Rows> Rows for (A, B) {
+    fn split_at(self, n: usize) -> (Self, Self) {
+        let (a0, a1) = self.0.split_at(n);
+        let (b0, b1) = self.1.split_at(n);
+        ((a0, b0), (a1, b1))
+    }
+}
+
+impl<A: Rows, B: Rows, C: Rows> Rows for (A, B, C) {
+    fn split_at(self, n: usize) -> (Self, Self) {
+        let (a0, a1) = self.0.split_at(n);
+        let (b0, b1) = self.1.split_at(n);
+        let (c0, c1) = self.2.split_at(n);
+        ((a0, b0, c0), (a1, b1, c1))
+    }
+}
+
+impl<A: Rows, B: Rows, C: Rows, D: Rows> Rows for (A, B, C, D) {
+    fn split_at(self, n: usize) -> (Self, Self) {
+        let (a0, a1) = self.0.split_at(n);
+        let (b0, b1) = self.1.split_at(n);
+        let (c0, c1) = self.2.split_at(n);
+        let (d0, d1) = self.3.split_at(n);
+        ((a0, b0, c0, d0), (a1, b1, c1, d1))
+    }
+}
+
+/**
+Call `f` for bands of the rows of an `xpix` x `ypix` image, each time with
+the range of rows in the band and `bufs` cut down to just those rows (so
+the band's first row is at index 0).
+*/
+#[cfg(not(feature = "threads"))]
+pub fn in_bands<R, F>(xpix: usize, ypix: usize, bufs: R, f: F)
+where R: Rows, F: Fn(Range<usize>, R) + Sync
+{
+    f(0..ypix, bufs.split_at(xpix * ypix).0);
+}
+
+/**
+Bands per available thread. The rows that take longest are bunched up
+wherever the set is, so more bands than threads keeps them all busy.
+*/
+#[cfg(feature = "threads")]
+const BANDS_PER_THREAD: usize = 4;
+
+#[cfg(all(test, feature = "threads"))]
+std::thread_local! {
+    /// How many threads `in_bands()` counts on, if not however many there are.
+    pub static THREADS: core::cell::Cell<Option<usize>> = const { core::cell::Cell::new(None) };
+}
+
+#[cfg(feature = "threads")]
+fn available_threads() -> usize {
+    #[cfg(test)]
+    if let Some(n) = THREADS.with(|t| t.get()) { return n; }
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+#[cfg(feature = "threads")]
+pub fn in_bands<R, F>(xpix: usize, ypix: usize, bufs: R, f: F)
+where R: Rows, F: Fn(Range<usize>, R) + Sync
+{
+    let threads = available_threads();
+    let n_bands = threads * BANDS_PER_THREAD;
+    let band_rows = ypix.div_ceil(n_bands);
+    if threads < 2 || band_rows == 0 {
+        f(0..ypix, bufs.split_at(xpix * ypix).0);
+        return;
+    }
+
+    let f = &f;
+    std::thread::scope(|s| {
+        let mut rest = bufs;
+        let mut start = 0;
+        while start < ypix {
+            let end = if start + band_rows < ypix { start + band_rows } else { ypix };
+            let (band, tail) = rest.split_at((end - start) * xpix);
+            s.spawn(move || f(start..end, band));
+            rest = tail;
+            start = end;
+        }
+    });
+}
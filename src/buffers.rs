@@ -0,0 +1,204 @@
+/*!
+The per-pixel buffers: `IMAGE`, `ITERMAP`, `INTERIOR`, `ORBIT`, and `STATE`.
+
+These used to be `static` arrays big enough for a 1920 x 1080 image. Now
+they share one region at the very end of the module's linear memory, which
+gets grown (with `memory.grow`) whenever an image needs more room. Nothing
+else in the module ever grows memory, so the region stays at the end and
+growing it never strands anything.
+
+The region is laid out as `capacity` pixels' worth of each buffer, one after
+the other, in the order above. `capacity` is always even, so `STATE` (whose
+`f64`s need 8-byte alignment) starts on a multiple of 8.
+*/
+
+use crate::{OrbitState, Pixels};
+
+/// bytes per pixel across the buffers before `STATE` (4 + 2 + 2 + 4)
+const FRONT_BYTES: usize = 12;
+
+/// bytes per pixel across all the buffers (`STATE` takes another 24)
+pub const BYTES_PER_PIXEL: usize = FRONT_BYTES + core::mem::size_of::<OrbitState>();
+
+/// Default limit on the size of the region, in bytes.
+pub const DEFAULT_CAP: usize = 1 << 30;
+
+/**
+Get the base address of the region, making sure it's at least `bytes`
+long. The base address never changes, so the current contents of the
+region stay put. Returns a null pointer if the memory can't be had.
+*/
+#[cfg(target_arch = "wasm32")]
+unsafe fn claim(bytes: usize) -> *mut u8 {
+    use core::arch::wasm32::{memory_grow, memory_size};
+    const PAGE: usize = 65_536;
+    static mut BASE: usize = 0;
+
+    if BASE == 0 { BASE = memory_size(0) * PAGE; }
+    let have = memory_size(0) * PAGE - BASE;
+    if bytes > have {
+        let pages = (bytes - have).div_ceil(PAGE);
+        if memory_grow(0, pages) == usize::MAX { return core::ptr::null_mut(); }
+    }
+    BASE as *mut u8
+}
+
+/**
+Anywhere but wasm there's no `memory.grow`, so a fixed pool (the size the
+old `static` buffers were) stands in for it.
+*/
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn claim(bytes: usize) -> *mut u8 {
+    const POOL_WORDS: usize = 1920 * 1080 * BYTES_PER_PIXEL / 8;
+    static mut POOL: [u64; POOL_WORDS] = [0; POOL_WORDS];
+
+    if bytes > POOL_WORDS * 8 { return core::ptr::null_mut(); }
+    core::ptr::addr_of_mut!(POOL) as *mut u8
+}
+
+pub struct PixelBuffers {
+    base: *mut u8,
+    /// number of pixels each buffer has room for
+    capacity: usize,
+    /// most bytes the region is allowed to take up
+    cap_bytes: usize,
+}
+
+impl PixelBuffers {
+    pub const fn new() -> PixelBuffers {
+        PixelBuffers {
+            base: core::ptr::null_mut(),
+            capacity: 0,
+            cap_bytes: DEFAULT_CAP,
+        }
+    }
+
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    pub fn set_cap(&mut self, bytes: usize) { self.cap_bytes = bytes; }
+
+    /**
+    Make sure each buffer has room for `npix` pixels. Anything already in
+    them is kept. Returns `false` (and changes nothing) if that would take
+    more than the cap, or more memory than there is.
+    */
+    pub unsafe fn reserve(&mut self, npix: usize) -> bool {
+        if npix <= self.capacity { return true; }
+        let npix = npix + npix % 2;
+        let bytes = match npix.checked_mul(BYTES_PER_PIXEL) {
+            Some(b) if b <= self.cap_bytes => b,
+            _ => { return false; },
+        };
+        let base = claim(bytes);
+        if base.is_null() { return false; }
+
+        // Every buffer starts further along in the new layout, so moving
+        // them last-to-first never overwrites one that hasn't moved yet.
+        let (old, new) = (self.capacity, npix);
+        if old > 0 {
+            let state = BYTES_PER_PIXEL - FRONT_BYTES;
+            core::ptr::copy(base.add(FRONT_BYTES * old), base.add(FRONT_BYTES * new), state * old);
+            core::ptr::copy(base.add(8 * old), base.add(8 * new), 4 * old);
+            core::ptr::copy(base.add(6 * old), base.add(6 * new), 2 * old);
+            core::ptr::copy(base.add(4 * old), base.add(4 * new), 2 * old);
+        }
+        self.base = base;
+        self.capacity = new;
+        true
+    }
+
+    pub fn image_ptr(&self) -> *mut u32 { self.base as *mut u32 }
+
+    /// `capacity` values of type `T`, starting `offset` bytes into the region.
+    unsafe fn slice_at<T>(&self, offset: usize) -> &'static mut [T] {
+        if self.base.is_null() { return &mut []; }
+        core::slice::from_raw_parts_mut(self.base.add(offset) as *mut T, self.capacity)
+    }
+
+    pub unsafe fn image(&self) -> &'static mut [u32] { self.slice_at(0) }
+
+    pub unsafe fn itermap(&self) -> &'static mut [u16] {
+        self.slice_at(4 * self.capacity)
+    }
+
+    pub unsafe fn interior(&self) -> &'static mut [u16] {
+        self.slice_at(6 * self.capacity)
+    }
+
+    pub unsafe fn orbit(&self) -> &'static mut [u32] {
+        self.slice_at(8 * self.capacity)
+    }
+
+    pub unsafe fn state(&self) -> &'static mut [OrbitState] {
+        self.slice_at(FRONT_BYTES * self.capacity)
+    }
+
+    /// `ITERMAP`, `INTERIOR`, `ORBIT`, and `STATE`, together.
+    pub unsafe fn pixels(&self) -> Pixels<'static> {
+        Pixels {
+            itermap: self.itermap(),
+            interior: self.interior(),
+            orbit: self.orbit(),
+            state: self.state(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cx;
+
+    fn fill(b: &PixelBuffers, n: usize) {
+        unsafe {
+            for i in 0..n {
+                b.image()[i] = i as u32;
+                b.itermap()[i] = (i + 1000) as u16;
+                b.interior()[i] = (i + 2000) as u16;
+                b.orbit()[i] = (i * 7) as u32;
+                b.state()[i] = OrbitState {
+                    z: Cx { re: i as f64, im: -(i as f64) },
+                    min_sqmod: 0.5 * i as f64,
+                };
+            }
+        }
+    }
+
+    fn intact(b: &PixelBuffers, n: usize) -> bool {
+        unsafe {
+            (0..n).all(|i| {
+                let st = b.state()[i];
+                b.image()[i] == i as u32
+                    && b.itermap()[i] == (i + 1000) as u16
+                    && b.interior()[i] == (i + 2000) as u16
+                    && b.orbit()[i] == (i * 7) as u32
+                    && st.z == Cx { re: i as f64, im: -(i as f64) }
+                    && st.min_sqmod == 0.5 * i as f64
+            })
+        }
+    }
+
+    #[test]
+    fn growing_keeps_contents() {
+        let _guard = crate::tests::lock();
+        let mut b = PixelBuffers::new();
+        unsafe {
+            assert!(b.reserve(9));
+            assert_eq!(b.capacity(), 10);
+            fill(&b, 9);
+            assert!(b.reserve(1001));
+            assert_eq!(b.capacity(), 1002);
+            assert!(intact(&b, 9));
+            fill(&b, 1001);
+            assert!(b.reserve(300));
+            assert_eq!(b.capacity(), 1002);
+            assert!(b.reserve(40_000));
+            assert!(intact(&b, 1001));
+
+            b.set_cap(50_000 * BYTES_PER_PIXEL);
+            assert!(!b.reserve(50_001));
+            assert_eq!(b.capacity(), 40_000);
+            assert!(intact(&b, 1001));
+        }
+    }
+}
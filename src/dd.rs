@@ -0,0 +1,284 @@
+/*!
+Double-double arithmetic, for zooming in past where `f64` gives out.
+
+A `Dd` is an unevaluated sum `hi + lo` of two `f64`s, with `lo` no more
+than half an ulp of `hi`, which makes about 106 bits of significand: enough
+for pixels some 10^-28 apart, where plain `f64` turns the image into blocks
+at around 10^-14. The algorithms are the usual ones (Dekker's and Knuth's,
+as in the QD library), with products split by hand, since there's no fused
+multiply-add without `std`. It's all a good deal slower than `f64`, so
+`DrawParams::precision()` only picks it when it has to.
+
+The iterators here take their points as `DdCx`s and otherwise work just
+like the `f64` ones (the polynomial one being the Julia set iterator), but
+they can't pick up where an orbit left off: `STATE` only has room for the
+`hi` halves.
+*/
+
+use crate::{Cx, IterResult, Limits, Poly};
+
+/// `2^27 + 1`, for splitting an `f64` into two 26-bit halves.
+const SPLITTER: f64 = 134_217_729.0;
+
+/// `a + b` exactly, as the rounded sum and the error.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// `two_sum()` for when `|a| >= |b|`.
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// `a` as two halves that can be multiplied without rounding.
+fn split(a: f64) -> (f64, f64) {
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+/// `a * b` exactly, as the rounded product and the error.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    let (ah, al) = split(a);
+    let (bh, bl) = split(b);
+    (p, ((ah * bh - p) + ah * bl + al * bh) + al * bl)
+}
+
+#[derive(Clone, Copy)]
+pub struct Dd { pub hi: f64, pub lo: f64 }
+
+impl Dd {
+    /// `hi + lo`, whatever sizes they are.
+    pub fn new(hi: f64, lo: f64) -> Dd {
+        let (hi, lo) = two_sum(hi, lo);
+        Dd { hi, lo }
+    }
+
+    pub const fn from(a: f64) -> Dd { Dd { hi: a, lo: 0.0 } }
+
+    fn norm((hi, lo): (f64, f64)) -> Dd {
+        let (hi, lo) = quick_two_sum(hi, lo);
+        Dd { hi, lo }
+    }
+
+    pub fn add(self, o: Dd) -> Dd {
+        let (s1, s2) = two_sum(self.hi, o.hi);
+        let (t1, t2) = two_sum(self.lo, o.lo);
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        Dd::norm((s1, s2 + t2))
+    }
+
+    pub fn neg(self) -> Dd { Dd { hi: -self.hi, lo: -self.lo } }
+
+    pub fn sub(self, o: Dd) -> Dd { self.add(o.neg()) }
+
+    pub fn mul(self, o: Dd) -> Dd {
+        let (p1, p2) = two_prod(self.hi, o.hi);
+        Dd::norm((p1, p2 + (self.hi * o.lo + self.lo * o.hi)))
+    }
+
+    pub fn mul_f64(self, b: f64) -> Dd {
+        let (p1, p2) = two_prod(self.hi, b);
+        Dd::norm((p1, p2 + self.lo * b))
+    }
+
+    pub fn sqr(self) -> Dd {
+        let (p1, p2) = two_prod(self.hi, self.hi);
+        Dd::norm((p1, p2 + 2.0 * self.hi * self.lo))
+    }
+
+    /// `2 * self`, which is exact.
+    pub fn twice(self) -> Dd { Dd { hi: 2.0 * self.hi, lo: 2.0 * self.lo } }
+}
+
+/// A complex number with double-double parts.
+#[derive(Clone, Copy)]
+pub struct DdCx { pub re: Dd, pub im: Dd }
+
+impl DdCx {
+    pub const fn from(c: &Cx) -> DdCx { DdCx { re: Dd::from(c.re), im: Dd::from(c.im) } }
+
+    fn add(&self, o: &DdCx) -> DdCx {
+        DdCx { re: self.re.add(o.re), im: self.im.add(o.im) }
+    }
+
+    fn mul(&self, o: &DdCx) -> DdCx {
+        DdCx {
+            re: self.re.mul(o.re).sub(self.im.mul(o.im)),
+            im: self.im.mul(o.re).add(self.re.mul(o.im)),
+        }
+    }
+
+    /// `self` times the plain complex number `o`.
+    fn mul_cx(&self, o: &Cx) -> DdCx {
+        DdCx {
+            re: self.re.mul_f64(o.re).sub(self.im.mul_f64(o.im)),
+            im: self.im.mul_f64(o.re).add(self.re.mul_f64(o.im)),
+        }
+    }
+
+    fn sqr(&self) -> DdCx {
+        DdCx { re: self.re.sqr().sub(self.im.sqr()), im: self.re.mul(self.im).twice() }
+    }
+
+    /// Rounded to a plain complex number.
+    pub fn cx(&self) -> Cx { Cx { re: self.re.hi, im: self.im.hi } }
+}
+
+/// `mandelbrot_iter()` for the point `c`, in double-double precision.
+pub fn mandelbrot_iter(c: &DdCx, limits: &Limits) -> IterResult {
+    let mut cur = DdCx::from(&Cx { re: 0.0, im: 0.0 });
+    let mut min_sqmod = f64::INFINITY;
+
+    for n in 0..limits.iter_limit {
+        cur = cur.sqr().add(c);
+        let z = cur.cx();
+        let sqmod = z.sqmod();
+        if sqmod < min_sqmod { min_sqmod = sqmod; }
+        if sqmod > limits.sq_mod_limit {
+            return IterResult::escaped(
+                n, &z, min_sqmod, limits.sq_mod_limit,
+                core::f64::consts::LN_2, limits.axis
+            );
+        }
+    }
+    IterResult::captive(limits.iter_limit, limits.metric, min_sqmod, &cur.cx())
+}
+
+/// `polynomial_iter()` for the point `start`, in double-double precision.
+pub fn polynomial_iter(start: &DdCx, poly: &Poly, limits: &Limits) -> IterResult {
+    let (coeffs, degree) = (poly.coeffs, poly.degree);
+    let mut cur = *start;
+    let mut min_sqmod = f64::INFINITY;
+
+    for n in 0..limits.iter_limit {
+        let mut new = DdCx::from(&Cx { re: 0.0, im: 0.0 });
+        let mut z   = DdCx::from(&Cx { re: 1.0, im: 0.0 });
+        for c in &coeffs[..degree] {
+            new = new.add(&z.mul_cx(c));
+            z = z.mul(&cur);
+        }
+        cur = new.add(&z.mul_cx(&coeffs[degree]));
+        let z = cur.cx();
+        let sqmod = z.sqmod();
+        if sqmod < min_sqmod { min_sqmod = sqmod; }
+        if sqmod > limits.sq_mod_limit {
+            return IterResult::escaped(
+                n, &z, min_sqmod, limits.sq_mod_limit, poly.ln_degree, limits.axis
+            );
+        }
+    }
+    IterResult::captive(limits.iter_limit, limits.metric, min_sqmod, &cur.cx())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed::Fixed;
+    use crate::DEFAULT_DRAW_PARAMS;
+
+    /// A little xorshift generator, so the same numbers come up every time.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// A whole number with up to 53 bits, either sign.
+        fn int(&mut self) -> f64 {
+            let m = (self.next() >> (11 + self.next() % 40)) as f64;
+            if self.next() & 1 == 0 { m } else { -m }
+        }
+
+        /// A full double-double somewhere between 2^-8 and 2^8, either sign.
+        fn dd(&mut self) -> Dd {
+            let scale = f64::powi(2.0, (self.next() % 17) as i32 - 8);
+            let hi = (1.0 + (self.next() >> 12) as f64 / 4_503_599_627_370_496.0) * scale;
+            let lo = self.int() * f64::EPSILON * f64::EPSILON * scale / 4.0;
+            let d = Dd::new(hi, lo);
+            if self.next() & 1 == 0 { d } else { d.neg() }
+        }
+    }
+
+    const LIMBS: usize = 8;
+
+    fn fixed(d: Dd) -> Fixed {
+        Fixed::from_f64(d.hi, LIMBS).add(&Fixed::from_f64(d.lo, LIMBS))
+    }
+
+    /// How far off `got` is from `want`, relative to `want`.
+    fn rel_err(got: Dd, want: &Fixed) -> f64 {
+        fixed(got).sub(want).to_f64().abs() / want.to_f64().abs()
+    }
+
+    #[test]
+    fn exact_pieces() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let (a, b) = (rng.int(), rng.int());
+            let (s, e) = two_sum(a, b);
+            assert_eq!(s as i128 + e as i128, a as i128 + b as i128);
+            let (p, e) = two_prod(a, b);
+            assert_eq!(p as i128 + e as i128, a as i128 * b as i128, "{} * {}", a, b);
+            let (hi, lo) = split(a);
+            assert_eq!(hi + lo, a);
+            // 26 bits each, so their products are exact.
+            assert_eq!(hi.to_bits() & ((1 << 27) - 1), 0);
+            assert_eq!(lo.to_bits() & ((1 << 27) - 1), 0);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let (a, b) = (rng.dd(), rng.dd());
+            let (fa, fb) = (fixed(a), fixed(b));
+            assert!(rel_err(a.add(b), &fa.add(&fb)) < 1e-30);
+            assert!(rel_err(a.sub(b), &fa.sub(&fb)) < 1e-30);
+            assert!(rel_err(a.mul(b), &fa.mul(&fb)) < 1e-30);
+            assert!(rel_err(a.sqr(), &fa.sqr()) < 1e-30);
+            assert!(rel_err(a.mul_f64(b.hi), &fa.mul(&fixed(Dd::from(b.hi)))) < 1e-30);
+        }
+    }
+
+    /**
+    A view 1e-20 wide is far too narrow for neighboring pixels to have
+    different `f64` coordinates, but in double-double they're all apart by
+    just the pixel spacing.
+    */
+    #[test]
+    fn deep_pixels_apart() {
+        const XPIX: usize = 60;
+        let dp = crate::DrawParams {
+            xpix: XPIX, ypix: 40, full_xpix: XPIX, full_ypix: 40,
+            x: -0.743_643_887_037_158_7, x_lo: 3.1e-18,
+            y: 0.131_825_904_205_311_97, y_lo: -1.7e-18,
+            width: 1e-20,
+            ..DEFAULT_DRAW_PARAMS
+        };
+        let spacing = dp.width / XPIX as f64;
+        let plain = (1..XPIX).filter(|&xp| dp.point_at(xp, 7).re != dp.point_at(xp - 1, 7).re);
+        assert!(plain.count() < 5);
+
+        for xp in 1..XPIX {
+            let (a, b) = (dp.point_dd(xp - 1, 7), dp.point_dd(xp, 7));
+            let step = b.re.sub(a.re);
+            assert!((step.hi - spacing).abs() < 1e-9 * spacing, "column {}", xp);
+            assert!(b.im.sub(a.im).hi == 0.0);
+        }
+        for yp in 1..40 {
+            let (a, b) = (dp.point_dd(3, yp - 1), dp.point_dd(3, yp));
+            let step = a.im.sub(b.im);
+            assert!((step.hi - spacing).abs() < 1e-9 * spacing, "row {}", yp);
+        }
+    }
+}
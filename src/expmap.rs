@@ -0,0 +1,90 @@
+/*!
+Exponential maps: log-polar strips, and turning them back into frames.
+
+An exponential map samples the plane on a grid that's polar around some
+center point, with the distance from the center shrinking _exponentially_
+down the strip. With `width` pixels per turn, every row is `2π/width` less
+(in natural log) than the one above it, so pixels come out square, and a
+strip a few thousand pixels tall covers a zoom of many orders of magnitude.
+Any frame of a zoom into the center can then be resampled out of it.
+
+The strip itself gets drawn like any other image, through
+`Warp::LogPolar` (see `render_exp_strip()`); this is the geometry on either
+side of that.
+*/
+
+use crate::{PixVal, fmath};
+
+use core::f64::consts::PI;
+
+/**
+Number of rows a strip `width` pixels across needs to cover the distances
+from `r_max` down to (at least) `r_min`.
+*/
+pub fn strip_height(width: usize, r_min: f64, r_max: f64) -> usize {
+    if r_min.is_nan() || r_max.is_nan() || r_min <= 0.0 || r_max <= r_min
+        || width == 0
+    {
+        return 0;
+    }
+    let rows = fmath::ln(r_max / r_min) * (width as f64) / (2.0 * PI);
+    let whole = fmath::floor(rows) as usize;
+    if (whole as f64) < rows { whole + 1 } else { whole }
+}
+
+/// A strip's `pixels`, its size, and the distance `r_max` at its top row.
+pub struct Strip<'a> {
+    pub pixels: &'a [u32],
+    pub width: usize,
+    pub height: usize,
+    pub r_max: f64,
+}
+
+/**
+Resample the `strip` into the `fw` x `fh` `frame`, which is centered on the
+strip's center and `2 * half_width` wide on the plane. Colors are blended
+bilinearly between the four nearest strip pixels. Frame pixels closer to
+the center than the bottom of the strip, or farther from it than the top,
+get `hole`.
+*/
+pub fn reproject(
+    strip: &Strip,
+    frame: &mut [u32], fw: usize, fh: usize, half_width: f64,
+    hole: u32,
+) {
+    let (sw, sh) = (strip.width, strip.height);
+    let step = 2.0 * half_width / (fw as f64);
+    let per_radian = (sw as f64) / (2.0 * PI);
+    let ln_r_max = fmath::ln(strip.r_max);
+    let pixel = |col: usize, row: usize| PixVal::from_u32(strip.pixels[row * sw + (col % sw)]);
+
+    for y in 0..fh {
+        let v = ((fh as f64) / 2.0 - (y as f64)) * step;
+        for x in 0..fw {
+            let u = ((x as f64) - (fw as f64) / 2.0) * step;
+            let sqr = u * u + v * v;
+            let row = if sqr > 0.0 {
+                (ln_r_max - 0.5 * fmath::ln(sqr)) * per_radian
+            } else {
+                f64::INFINITY
+            };
+            if row.is_nan() || row < 0.0 || row > ((sh - 1) as f64) {
+                frame[y * fw + x] = hole;
+                continue;
+            }
+            let turn = fmath::atan2(v, u);
+            let col = (if turn < 0.0 { turn + 2.0 * PI } else { turn }) * per_radian;
+
+            let (c0, r0) = (fmath::floor(col), fmath::floor(row));
+            let (fc, fr) = ((col - c0) as f32, (row - r0) as f32);
+            let (c0, r0) = (c0 as usize, r0 as usize);
+            let r1 = if r0 + 1 < sh { r0 + 1 } else { r0 };
+
+            let mut p = pixel(c0, r0).scale((1.0 - fc) * (1.0 - fr));
+            p.add(&pixel(c0 + 1, r0).scale(fc * (1.0 - fr)));
+            p.add(&pixel(c0, r1).scale((1.0 - fc) * fr));
+            p.add(&pixel(c0 + 1, r1).scale(fc * fr));
+            frame[y * fw + x] = p.to_u32();
+        }
+    }
+}
@@ -0,0 +1,353 @@
+/*!
+Fixed-point numbers with as many bits as a deep zoom needs.
+
+A `Fixed` is a sign and a magnitude, the magnitude being `len` 32-bit
+limbs, most significant first: the first is the whole part, and the rest
+are fractions of it, each 2^32 times finer than the last. That's all the
+perturbation iterator (see the `perturb` module) needs for its reference
+orbits, which never get further from the origin than the escape radius.
+Products are truncated to `len` limbs, so they're a little off in the last
+limb, which is why `limbs_for()` throws in a couple of spare ones.
+
+Every number in a calculation should have the same `len`; the result of an
+operation has the `len` of its left operand.
+
+They're also how a view's coordinates get to be more precise than any
+`f64` (or double-double) can hold: `parse()` reads them from decimal.
+*/
+
+use crate::dd::Dd;
+
+/// The most limbs a `Fixed` can have: enough for pixels down to about `1e-300`.
+pub const MAX_LIMBS: usize = 36;
+
+#[derive(Clone, Copy)]
+pub struct Fixed {
+    neg: bool,
+    len: usize,
+    limbs: [u32; MAX_LIMBS],
+}
+
+/**
+How many limbs it takes to tell apart points `spacing` apart, with 64 bits
+to spare for rounding to pile up in over the iterations.
+*/
+pub fn limbs_for(spacing: f64) -> usize {
+    let exp = ((spacing.to_bits() >> 52) & 0x7ff) as i64 - 1023;
+    if spacing == 0.0 || exp <= -1023 { return MAX_LIMBS; }
+    let bits = (64 - exp).max(64) as usize;
+    (1 + bits.div_ceil(32)).min(MAX_LIMBS)
+}
+
+impl Fixed {
+    pub const fn zero(len: usize) -> Fixed {
+        Fixed { neg: false, len, limbs: [0; MAX_LIMBS] }
+    }
+
+    /**
+    `x` with `len` limbs, exactly if they have room for all its bits (and
+    truncated if not). The whole part of `x` has to fit in 32 bits.
+    */
+    pub fn from_f64(x: f64, len: usize) -> Fixed {
+        let mut f = Fixed::zero(len);
+        let bits = x.to_bits();
+        let raw_exp = ((bits >> 52) & 0x7ff) as i64;
+        if raw_exp == 0 || raw_exp == 0x7ff { return f; }
+        let mant = (bits & ((1 << 52) - 1)) | (1 << 52);
+        // x is mant * 2^shift, and the last limb's unit is 2^(-32 * (len - 1)).
+        let shift = raw_exp - 1075 + 32 * (len as i64 - 1);
+        for (i, limb) in f.limbs[..len].iter_mut().enumerate() {
+            let lo = 32 * (len - 1 - i) as i64 - shift;
+            *limb = if lo >= 64 || lo <= -32 { 0 }
+                else if lo >= 0 { (mant >> lo) as u32 }
+                else { (mant << -lo) as u32 };
+        }
+        f.neg = x < 0.0;
+        f
+    }
+
+    /**
+    The decimal number `s` (like `-0.75`, `1.5e-40`, or `+3`) with `len`
+    limbs, truncated, or `None` if it isn't one or its whole part doesn't
+    fit in 32 bits.
+    */
+    pub fn parse(s: &[u8], len: usize) -> Option<Fixed> {
+        let (neg, s) = match s.first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (mant, exp) = match s.iter().position(|&c| c == b'e' || c == b'E') {
+            Some(i) => (&s[..i], parse_exponent(&s[i + 1..])?),
+            None => (s, 0),
+        };
+        let point = mant.iter().position(|&c| c == b'.').unwrap_or(mant.len());
+        let n_digits = mant.len() - (point < mant.len()) as usize;
+        // Digit i (not counting the point) is worth 10^(n_whole - 1 - i).
+        let digit = |i: usize| mant[if i < point { i } else { i + 1 }].wrapping_sub(b'0');
+        if n_digits == 0 || (0..n_digits).any(|i| digit(i) > 9) { return None; }
+        let n_whole = point as i64 + exp;
+
+        let mut f = Fixed::zero(len);
+        for i in (0..n_digits).rev() {
+            if (i as i64) < n_whole { break; }
+            f.limbs[0] = digit(i) as u32;
+            f.div_small(10);
+        }
+        // Leading zeros past the point; after enough of them there's nothing left.
+        for _ in 0..(-n_whole).clamp(0, 12 * MAX_LIMBS as i64) { f.div_small(10); }
+
+        let mut whole = 0u32;
+        for i in 0..n_whole.max(0) as usize {
+            let d = if i < n_digits { digit(i) as u32 } else { 0 };
+            whole = whole.checked_mul(10)?.checked_add(d)?;
+        }
+        f.limbs[0] = whole;
+        f.neg = neg && !f.is_zero();
+        Some(f)
+    }
+
+    /// Rounded to the nearest `f64`, give or take an ulp.
+    pub fn to_f64(self) -> f64 {
+        let first = match self.limbs[..self.len].iter().position(|&l| l != 0) {
+            Some(i) => i,
+            None => { return 0.0; },
+        };
+        let mut x = 0.0;
+        for &limb in self.limbs[first..self.len.min(first + 3)].iter().rev() {
+            x = x * (1.0 / 4_294_967_296.0) + limb as f64;
+        }
+        for _ in 0..first { x *= 1.0 / 4_294_967_296.0; }
+        if self.neg { -x } else { x }
+    }
+
+    /**
+    The double-double closest to `self` (give or take), and what's left of
+    `self` past it.
+    */
+    pub fn split_dd(&self) -> (Dd, Fixed) {
+        let hi = self.to_f64();
+        let rest = self.sub(&Fixed::from_f64(hi, self.len));
+        let lo = rest.to_f64();
+        (Dd::new(hi, lo), rest.sub(&Fixed::from_f64(lo, self.len)))
+    }
+
+    /// `self` with only its first `len` limbs (which can't be more than it has).
+    pub fn truncated(&self, len: usize) -> Fixed {
+        let mut r = *self;
+        r.limbs[len..].fill(0);
+        r.len = len;
+        r.neg = self.neg && !r.is_zero();
+        r
+    }
+
+    /// Divide the magnitude by `d`, truncating.
+    fn div_small(&mut self, d: u32) {
+        let mut rem = 0u64;
+        for limb in self.limbs[..self.len].iter_mut() {
+            let cur = (rem << 32) | *limb as u64;
+            *limb = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+    }
+
+    fn is_zero(&self) -> bool { self.limbs[..self.len].iter().all(|&l| l == 0) }
+
+    /// Compare magnitudes.
+    fn mag_ge(&self, o: &Fixed) -> bool {
+        for i in 0..self.len {
+            if self.limbs[i] != o.limbs[i] { return self.limbs[i] > o.limbs[i]; }
+        }
+        true
+    }
+
+    /// `|self| + |o|`, with `self`'s sign.
+    fn mag_add(&self, o: &Fixed) -> Fixed {
+        let mut r = Fixed { neg: self.neg, ..Fixed::zero(self.len) };
+        let mut carry = 0u64;
+        for i in (0..self.len).rev() {
+            let t = self.limbs[i] as u64 + o.limbs[i] as u64 + carry;
+            r.limbs[i] = t as u32;
+            carry = t >> 32;
+        }
+        r
+    }
+
+    /// `|self| - |o|`, which had better not be negative, with sign `neg` (unless it's 0).
+    fn mag_sub(&self, o: &Fixed, neg: bool) -> Fixed {
+        let mut r = Fixed::zero(self.len);
+        let mut borrow = 0i64;
+        for i in (0..self.len).rev() {
+            let mut t = self.limbs[i] as i64 - o.limbs[i] as i64 - borrow;
+            borrow = if t < 0 { t += 1 << 32; 1 } else { 0 };
+            r.limbs[i] = t as u32;
+        }
+        r.neg = neg && !r.is_zero();
+        r
+    }
+
+    pub fn add(&self, o: &Fixed) -> Fixed {
+        if self.neg == o.neg { self.mag_add(o) }
+        else if self.mag_ge(o) { self.mag_sub(o, self.neg) }
+        else { o.mag_sub(self, o.neg) }
+    }
+
+    pub fn neg(&self) -> Fixed {
+        Fixed { neg: !self.neg && !self.is_zero(), ..*self }
+    }
+
+    pub fn sub(&self, o: &Fixed) -> Fixed { self.add(&o.neg()) }
+
+    /// `self * o`, truncated.
+    pub fn mul(&self, o: &Fixed) -> Fixed {
+        let n = self.len;
+        // Limb k of the full product is worth 2^(-32 * (k - 1)).
+        let mut p = [0u32; 2 * MAX_LIMBS];
+        for i in (0..n).rev() {
+            if self.limbs[i] == 0 { continue; }
+            let a = self.limbs[i] as u64;
+            let mut carry = 0u64;
+            for j in (0..n).rev() {
+                let k = i + j + 1;
+                let t = a * o.limbs[j] as u64 + p[k] as u64 + carry;
+                p[k] = t as u32;
+                carry = t >> 32;
+            }
+            p[i] = carry as u32;
+        }
+        let mut r = Fixed::zero(n);
+        r.limbs[..n].copy_from_slice(&p[1..=n]);
+        r.neg = self.neg != o.neg && !r.is_zero();
+        r
+    }
+
+    pub fn sqr(&self) -> Fixed { Fixed { neg: false, ..self.mul(self) } }
+
+    /// `2 * self`, which is exact (as long as it fits).
+    pub fn twice(&self) -> Fixed { self.mag_add(self) }
+}
+
+/// The exponent after the `e` of a decimal number (capped, since `Fixed`s run out of digits anyway).
+fn parse_exponent(s: &[u8]) -> Option<i64> {
+    let (neg, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if s.is_empty() { return None; }
+    let mut exp = 0i64;
+    for &c in s {
+        if !c.is_ascii_digit() { return None; }
+        exp = (exp * 10 + (c - b'0') as i64).min(100_000);
+    }
+    Some(if neg { -exp } else { exp })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limbs(f: &Fixed) -> &[u32] { &f.limbs[..f.len] }
+
+    #[test]
+    fn products() {
+        let f = |x: f64| Fixed::from_f64(x, 4);
+        assert_eq!(f(1.5).mul(&f(1.5)).to_f64(), 2.25);
+        assert_eq!(f(-0.75).mul(&f(0.5)).to_f64(), -0.375);
+        assert_eq!(f(3.0).mul(&f(-3.0)).to_f64(), -9.0);
+        assert_eq!(f(-0.125).mul(&f(-12.0)).to_f64(), 1.5);
+        assert_eq!(f(2f64.powi(-40)).mul(&f(2f64.powi(-40))).to_f64(), 2f64.powi(-80));
+        // (1 + 2^-32)^2 = 1 + 2^-31 + 2^-64, exactly.
+        let x = f(1.0 + 2f64.powi(-32));
+        assert_eq!(limbs(&x.mul(&x)), &[1, 2, 1, 0]);
+        assert_eq!(limbs(&x.sqr()), &[1, 2, 1, 0]);
+        // Past the last limb, it's truncated.
+        let tiny = f(2f64.powi(-60));
+        assert_eq!(limbs(&tiny.mul(&tiny)), &[0, 0, 0, 0]);
+        assert!(!f(-1.0).mul(&tiny.mul(&tiny)).neg);
+    }
+
+    #[test]
+    fn sums_across_signs() {
+        let f = |x: f64| Fixed::from_f64(x, 3);
+        for (a, b) in [(1.5, -0.25), (-1.5, 0.25), (0.25, -1.5), (-0.25, 1.5), (-2.0, -0.75)] {
+            assert_eq!(f(a).add(&f(b)).to_f64(), a + b, "{} + {}", a, b);
+            assert_eq!(f(a).sub(&f(b)).to_f64(), a - b, "{} - {}", a, b);
+        }
+        // A borrow all the way up from the last limb.
+        let small = 2f64.powi(-64);
+        assert_eq!(limbs(&f(1.0).sub(&f(small))), &[0, u32::MAX, u32::MAX]);
+        assert_eq!(limbs(&f(-small).add(&f(1.0))), &[0, u32::MAX, u32::MAX]);
+        // Zero is never negative.
+        for x in [0.75, -0.75] {
+            assert!(!f(x).sub(&f(x)).neg && !f(x).neg().add(&f(x)).neg);
+        }
+        assert!(!f(0.0).neg().neg);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut x = 1.0f64;
+        for i in 0..4000 {
+            // All sorts of bit patterns, from 2^31 down to 2^-200.
+            x = x * 1.618_033_988_749_895 % 2_147_483_648.0 + 1e-60;
+            let y = if i % 2 == 0 { x } else { -x * 2f64.powi(-(i % 231)) };
+            assert_eq!(Fixed::from_f64(y, MAX_LIMBS).to_f64(), y, "{}", y);
+        }
+        // Bits past the last limb are dropped.
+        assert_eq!(Fixed::from_f64(2f64.powi(-100), 3).to_f64(), 0.0);
+        assert_eq!(Fixed::from_f64(1.0 + 2f64.powi(-70), 3).to_f64(), 1.0);
+    }
+
+    #[test]
+    fn limbs_needed() {
+        assert_eq!(limbs_for(1.0), 3);
+        assert_eq!(limbs_for(1e10), 3);
+        assert_eq!(limbs_for(1e-20), 6);
+        assert_eq!(limbs_for(2f64.powi(-100)), 7);
+        assert_eq!(limbs_for(1e-300), 35);
+        assert_eq!(limbs_for(1e-310), MAX_LIMBS);
+        assert_eq!(limbs_for(0.0), MAX_LIMBS);
+    }
+
+    #[test]
+    fn parses() {
+        let p = |s: &str| Fixed::parse(s.as_bytes(), MAX_LIMBS);
+        let f = |s: &str| p(s).unwrap().to_f64();
+        let x = p("-0.75").unwrap();
+        assert!(x.neg && x.limbs[0] == 0 && x.limbs[1] == 0xc000_0000);
+        assert_eq!(f("1e3"), 1000.0);
+        assert_eq!(f("+3"), 3.0);
+        assert_eq!(f("2.5E+1"), 25.0);
+        assert_eq!(f(".5"), 0.5);
+        assert_eq!(f("7."), 7.0);
+        assert_eq!(f("0.000625e2"), 0.0625);
+        assert!((f("1.5e-40") / 1.5e-40 - 1.0).abs() < 1e-15);
+        assert!((f("-0.1234567890123456789e-250") / -1.234_567_890_123_456_8e-251 - 1.0).abs() < 1e-15);
+        // Tenths in binary, truncated.
+        assert_eq!(limbs(&Fixed::parse(b"0.1", 3).unwrap()), &[0, 0x1999_9999, 0x9999_9999]);
+
+        for bad in ["", ".", "1.2.3", "e5", "-", "+", "-.", "1e", "1e-", "1e+", "1e5e5", "1x", " 1", "--1", "0x10", "1,5"] {
+            assert!(p(bad).is_none(), "{:?}", bad);
+        }
+
+        // The whole part has to fit in 32 bits.
+        assert_eq!(f("4294967295"), 4_294_967_295.0);
+        assert_eq!(f("-4294967295.5"), -4_294_967_295.5);
+        for big in ["4294967296", "-4294967296", "4.294967296e9", "0.0000000004294967296e19", "99999999999"] {
+            assert!(p(big).is_none(), "{:?}", big);
+        }
+
+        // Huge exponents either overflow or leave nothing, without taking forever.
+        for big in ["1e100000", "1e99999999999999999999999", "-0.0001e999999999999"] {
+            assert!(p(big).is_none(), "{:?}", big);
+        }
+        for tiny in ["1e-100000", "-1e-99999999999999999999999", "123456e-500"] {
+            let x = p(tiny).unwrap();
+            assert!(x.is_zero() && !x.neg, "{:?}", tiny);
+        }
+        assert_eq!(f("0e99999999999999999999"), 0.0);
+        assert_eq!(f("-0"), 0.0);
+        assert!(!p("-0").unwrap().neg);
+    }
+}
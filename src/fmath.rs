@@ -0,0 +1,206 @@
+/*!
+Floating-point functions that `core` doesn't provide.
+
+Without `std` there's no `f64::sqrt()` and friends, and I don't want to pull
+in a dependency just for a handful of functions, so here they are. None of
+these are as fast or as exact as the real thing, but they're plenty good for
+picking colors.
+*/
+
+/**
+Square root by Newton's method. The initial guess comes from halving the
+exponent in the bit representation, which is close enough that a few
+iterations get it to full `f64` precision. Negative arguments (and NaN)
+return `0.0`.
+*/
+pub fn sqrt(x: f64) -> f64 {
+    if x.is_nan() || x <= 0.0 { return 0.0; }
+    if x == f64::INFINITY { return x; }
+
+    let mut y = f64::from_bits((x.to_bits() >> 1) + (0x3FF0_0000_0000_0000 >> 1));
+    for _ in 0..5 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+/**
+Largest integer not greater than `x`. Anything too big to have a fractional
+part is returned as-is.
+*/
+pub fn floor(x: f64) -> f64 {
+    if x.is_nan() || x.abs() >= 4_503_599_627_370_496.0 { return x; }
+    let t = (x as i64) as f64;
+    if t > x { t - 1.0 } else { t }
+}
+
+/// π/2 split into a leading part and a correction, for range reduction.
+const PIO2_HI: f64 = core::f64::consts::FRAC_PI_2;
+const PIO2_LO: f64 = 6.123_233_995_736_766e-17;
+
+/**
+Reduce `x` to `r` in [-π/4, π/4] such that `x = r + k·π/2`; returns `r`
+and `k mod 4`.
+*/
+fn reduce_quadrant(x: f64) -> (f64, u8) {
+    let k = floor(x / PIO2_HI + 0.5);
+    let r = (x - k * PIO2_HI) - k * PIO2_LO;
+    (r, ((k as i64) & 3) as u8)
+}
+
+/// Taylor series for sine; good to about 1e-17 on [-π/4, π/4].
+fn sin_kernel(r: f64) -> f64 {
+    let r2 = r * r;
+    let mut term = r;
+    let mut sum = r;
+    for n in 1..9 {
+        let k = (2 * n) as f64;
+        term = -term * r2 / (k * (k + 1.0));
+        sum += term;
+    }
+    sum
+}
+
+/// Taylor series for cosine; good to about 1e-17 on [-π/4, π/4].
+fn cos_kernel(r: f64) -> f64 {
+    let r2 = r * r;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..9 {
+        let k = (2 * n) as f64;
+        term = -term * r2 / ((k - 1.0) * k);
+        sum += term;
+    }
+    sum
+}
+
+/// Sine of `x` (in radians).
+pub fn sin(x: f64) -> f64 {
+    let (r, q) = reduce_quadrant(x);
+    match q {
+        0 =>  sin_kernel(r),
+        1 =>  cos_kernel(r),
+        2 => -sin_kernel(r),
+        _ => -cos_kernel(r),
+    }
+}
+
+/// Cosine of `x` (in radians).
+pub fn cos(x: f64) -> f64 {
+    let (r, q) = reduce_quadrant(x);
+    match q {
+        0 =>  cos_kernel(r),
+        1 => -sin_kernel(r),
+        2 => -cos_kernel(r),
+        _ =>  sin_kernel(r),
+    }
+}
+
+/// ln(2) split into a leading part and a correction, for range reduction.
+const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+
+/**
+Natural logarithm. Splits `x` into a mantissa in [√½, √2) and a power of
+two, then uses the series for atanh on the mantissa. Non-positive arguments
+give negative infinity.
+*/
+pub fn ln(x: f64) -> f64 {
+    if x.is_nan() || x <= 0.0 { return f64::NEG_INFINITY; }
+    if x == f64::INFINITY { return x; }
+
+    // Scale subnormals up so the exponent bits mean something.
+    let (x, bias) = if x < f64::MIN_POSITIVE { (x * 18_014_398_509_481_984.0, -54) }
+                    else { (x, 0) };
+    let bits = x.to_bits();
+    let mut e = ((bits >> 52) & 0x7FF) as i64 - 1023 + bias;
+    let mut m = f64::from_bits((bits & 0x000F_FFFF_FFFF_FFFF) | 0x3FF0_0000_0000_0000);
+    if m > core::f64::consts::SQRT_2 { m /= 2.0; e += 1; }
+
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let mut term = s;
+    let mut sum = s;
+    for n in 1..12 {
+        term *= s2;
+        sum += term / ((2 * n + 1) as f64);
+    }
+    let ef = e as f64;
+    (ef * LN2_HI) + (2.0 * sum + ef * LN2_LO)
+}
+
+/// e to the `x`.
+pub fn exp(x: f64) -> f64 {
+    if x > 709.0 { return f64::INFINITY; }
+    if x < -745.0 { return 0.0; }
+    if x.is_nan() { return x; }
+
+    let k = floor(x / LN2_HI + 0.5);
+    let r = (x - k * LN2_HI) - k * LN2_LO;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..14 {
+        term = term * r / (n as f64);
+        sum += term;
+    }
+    // Multiply by 2^k in two steps so neither factor over- or underflows.
+    let k = k as i64;
+    let k1 = k / 2;
+    let k2 = k - k1;
+    sum * f64::from_bits(((k1 + 1023) as u64) << 52)
+        * f64::from_bits(((k2 + 1023) as u64) << 52)
+}
+
+/// `x` to the `y`, for positive `x`; anything else gives `0.0`.
+pub fn powf(x: f64, y: f64) -> f64 {
+    if x.is_nan() || x <= 0.0 { return 0.0; }
+    exp(y * ln(x))
+}
+
+/// Cube root, by Newton's method.
+pub fn cbrt(x: f64) -> f64 {
+    if x == 0.0 || x.is_nan() || x.abs() == f64::INFINITY { return x; }
+    let a = x.abs();
+    let mut y = f64::from_bits(a.to_bits() / 3 + 0x2A9F_7893_782D_A1CE);
+    for _ in 0..6 {
+        y = y - (y * y * y - a) / (3.0 * y * y);
+    }
+    if x < 0.0 { -y } else { y }
+}
+
+/// tan(π/8), the cutoff for `atan()`'s range reduction.
+const TAN_PI_8: f64 = 0.414_213_562_373_095_1;
+
+/**
+Arctangent. Arguments bigger than 1 get reflected through π/2, and then
+arguments bigger than tan(π/8) get shifted by π/4, so the series only ever
+sees small values.
+*/
+pub fn atan(x: f64) -> f64 {
+    use core::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+    if x.is_nan() { return x; }
+    let (t, sign) = if x < 0.0 { (-x, -1.0) } else { (x, 1.0) };
+    let (t, base) = if t > 1.0 { (-1.0 / t, FRAC_PI_2) } else { (t, 0.0) };
+    let (t, base) = if t > TAN_PI_8 { ((t - 1.0) / (t + 1.0), base + FRAC_PI_4) }
+                    else if t < -TAN_PI_8 { ((t + 1.0) / (1.0 - t), base - FRAC_PI_4) }
+                    else { (t, base) };
+    let t2 = t * t;
+    let mut term = t;
+    let mut sum = t;
+    for n in 1..21 {
+        term = -term * t2;
+        sum += term / ((2 * n + 1) as f64);
+    }
+    sign * (base + sum)
+}
+
+/// The angle of the point (`x`, `y`) from the positive x axis, in (-π, π].
+pub fn atan2(y: f64, x: f64) -> f64 {
+    use core::f64::consts::{FRAC_PI_2, PI};
+    if x > 0.0 { atan(y / x) }
+    else if x < 0.0 {
+        if y < 0.0 { atan(y / x) - PI } else { atan(y / x) + PI }
+    } else if y > 0.0 { FRAC_PI_2 }
+    else if y < 0.0 { -FRAC_PI_2 }
+    else { 0.0 }
+}
@@ -1,8 +1,11 @@
 /*!
 WASM module for coloring Julia sets based on divergence speed.
 
-All arrays are of static size to avoid allocation. Current limitations are:
-  * image size 1920 x 1080 pixels
+Almost all arrays are of static size to avoid allocation. The exception is
+the per-pixel buffers, which grow (see the `buffers` module) to fit whatever
+size image is asked for, up to a memory cap. Current limitations are:
+  * image size limited by the memory cap (1 GiB by default; see
+    `set_memory_cap()`), at 36 bytes per pixel
   * 16 gradients
   * 65,535 individual color steps
 
@@ -10,6 +13,11 @@ All functions are only ever called single-threadedly from Javascript, so
 anything marked `unsafe` actually isn't. This is basically C. I've still
 tried to minimize the amount of actual code in `unsafe` blocks, though.
 
+Native builds can be made with the `threads` feature, which splits the
+per-pixel work into bands of rows on separate threads (see the `bands`
+module). The exported functions still have to be called from one thread
+at a time.
+
 To render an image in an HTML `<canvas>` using this module:
   * Load this wasm module into your JS script. If you want this module to
     signal when it panics, bind your function to indicate panic to the
@@ -28,53 +36,106 @@ To render an image in an HTML `<canvas>` using this module:
     gradients in your color map.
   * Call `update_color_map()` to process those gradients into an array of
     individual colors used in the next step.
+  * If you want the points that never diverge shaded instead of filled with
+    a single default color, do the same with `set_interior_gradient()`,
+    `set_n_interior_gradients()`, and `update_interior_map()`, and pick what
+    to shade them by with `set_interior_metric()`.
+  * Or, instead of setting gradients one at a time, call `load_preset()` to
+    use one of the built-in palettes (see `n_presets()` and `preset_name()`),
+    or `random_palette()` to have one generated from a seed, then update the
+    color map.
   * If you are using the polynomial iterator, call `set_coeff(n, re, im)`
     for each complex coefficient in your polynomial, then call
     `set_n_coeffs(n)` to let the module know how many coefficients your
     polynomial has.
   * Call `redraw(xpix, ypix, x, y, width, use_poly_iter)` to write image
-    data to the exposed `IMAGE` buffer.
-  * Finally, wrap the `IMAGE` buffer in a `Uint8ClampedArray`, and use the
-    `<canvas>` context's `.putImageData()` method to insert the image into
-    the canvas.
+    data to the `IMAGE` buffer.
+  * Finally, get the location of the `IMAGE` buffer from `image_ptr()`,
+    wrap it in a `Uint8ClampedArray` (over the module's current
+    `memory.buffer`, which gets replaced when the image gets bigger), and
+    use the `<canvas>` context's `.putImageData()` method to insert the
+    image into the canvas.
 */
 
-#![no_std]
+#![cfg_attr(not(any(test, feature = "threads")), no_std)]
+
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+compile_error!("the `threads` feature is for native builds only");
+
+mod bands;
+mod boundary;
+mod buffers;
+mod dd;
+mod expmap;
+mod fixed;
+mod fmath;
+mod palette;
+mod perturb;
+mod reuse;
+mod simd;
+mod single;
+mod subdivide;
+mod symmetry;
+mod warp;
+
+use core::ops::Range;
+
+use buffers::PixelBuffers;
+
+use dd::Dd;
+use fixed::Fixed;
+
+use palette::{PaletteStyle, Rgb};
+
+use warp::Warp;
 
 /// This function is exposed by the JS; it is intended to signal a panic.
+#[cfg(not(any(test, feature = "threads")))]
 extern { fn pnk(); }
 
-/// Just signals a panic and then goes busy-wait catatonic.
+/**
+Just signals a panic and then goes busy-wait catatonic. (With `std`, for
+the `threads` feature or the tests, panics are handled the usual way.)
+*/
+#[cfg(not(any(test, feature = "threads")))]
 #[panic_handler]
 fn handle_panic(_: &core::panic::PanicInfo) -> ! {
     unsafe { pnk(); }
     loop {};
 }
 
-/// largest allowable image width
-const MAX_WIDTH: usize  = 1920;
-/// largest allowable image height
-const MAX_HEIGHT: usize = 1080;
-/// image data buffer size calculated from `MAX_WIDTH` and `MAX_HEIGHT`
-const IMAGE_SIZE: usize = MAX_WIDTH * MAX_HEIGHT;
 /// maximum number gradients in the color map
 const MAX_GRADIENTS: usize = 16;
 /// maximum number of individual color steps in the color map
 const COLOR_MAP_LENGTH: usize = 65_536;
 /// maximum number of polynomial coefficients (unused!)
 const MAX_COEFFS: usize = 7;
+/// maximum number of texels in the 2D palette `TEXTURE`
+const TEXTURE_SIZE: usize = 1024 * 1024;
 
 /**
-The actual data that gets passed to the HTML canvas in a Javascript
-`Uint8ClampedArray`. Format of each u32 is `0xAABBGGRR`.
-*/
-#[no_mangle]
-static mut IMAGE:   [u32; IMAGE_SIZE] = [0; IMAGE_SIZE];
-/**
-Output of the "iterator" stage; value is the number of steps it takes any
-given pixel's point do diverge.
+The per-pixel buffers. There are four, all of them laid out row by row,
+`DRAW_PARAMS.xpix` pixels to a row:
+  * `IMAGE`: the actual data that gets passed to the HTML canvas in a
+    Javascript `Uint8ClampedArray`. Format of each u32 is `0xAABBGGRR`.
+  * `ITERMAP`: output of the "iterator" stage; value is the number of steps
+    it takes any given pixel's point do diverge.
+  * `INTERIOR`: output of the "iterator" stage for points that _don't_
+    diverge; value is the interior metric (see `InteriorMetric`) scaled to
+    the full range of a `u16`. Meaningless for points that do diverge.
+  * `ORBIT`: output of the "iterator" stage for points that _do_ diverge,
+    but only when coloring from `TEXTURE` (see `TextureAxis`). The low 16
+    bits are the fractional part of the point's smoothed iteration count,
+    and the high 16 bits are its position along the texture's other axis,
+    both scaled to the full range of a `u16`.
 */
-static mut ITERMAP: [u16; IMAGE_SIZE] = [0; IMAGE_SIZE];
+static mut BUFFERS: PixelBuffers = PixelBuffers::new();
+
+/// `&mut BUFFERS`, made from a raw pointer rather than a reference to the `static mut`.
+unsafe fn buffers() -> &'static mut PixelBuffers {
+    &mut *core::ptr::addr_of_mut!(BUFFERS)
+}
+
 /**
 The collection of actual color values. `COLOR_MAP[n]` is the color a pixel
 will be colored when its point takes `n` iterations to exceed the modulus
@@ -101,6 +162,41 @@ static mut N_GRADIENTS: usize = 7;
 /// The color to color points that iterate past the end of the gradient.
 static mut DEFAULT_COLOR: u32 = 0xFF_00_00_00;
 
+/**
+The interior gradients, laid out exactly like the ones above. These are used
+to color the points that iterate past the end of the color map; if there
+aren't any, those points just get `DEFAULT_COLOR`.
+*/
+static mut IR0:      [u8; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+static mut IR1:      [u8; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+static mut IG0:      [u8; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+static mut IG1:      [u8; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+static mut IB0:      [u8; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+static mut IB1:      [u8; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+static mut ISHADES: [u16; MAX_GRADIENTS] = [0; MAX_GRADIENTS];
+/// The number of gradients in the current interior color scheme.
+static mut N_INTERIOR_GRADIENTS: usize = 0;
+/**
+The interior color values. A non-diverging pixel whose `INTERIOR` value is
+`v` gets `INTERIOR_MAP[v * CURRENT_INTERIOR_MAP_LENGTH / 65536]`.
+*/
+static mut INTERIOR_MAP: [u32; COLOR_MAP_LENGTH] = [0; COLOR_MAP_LENGTH];
+/// The number of shades in the last calculated interior color map.
+static mut CURRENT_INTERIOR_MAP_LENGTH: usize = 0;
+
+/**
+A 2D palette, as an alternative to `COLOR_MAP` for diverging points. Same
+pixel format as `IMAGE`, stored row by row, `TEXTURE_WIDTH` texels to a row;
+write it directly from JS. Across, it's indexed by smoothed iteration count,
+wrapping every `TEXTURE_PERIOD` iterations; down, by whatever `TextureAxis`
+is in use.
+*/
+#[no_mangle]
+static mut TEXTURE: [u32; TEXTURE_SIZE] = [0; TEXTURE_SIZE];
+static mut TEXTURE_WIDTH:  usize = 1;
+static mut TEXTURE_HEIGHT: usize = 1;
+static mut TEXTURE_PERIOD: f32 = 64.0;
+
 /**
 The number of shades in the last _calculated_ color map. This should be the
 number used by the _currently running_ coloring routine.
@@ -118,7 +214,7 @@ This is obviously a complex number abstraction. I only introduced it because
 I was screwing up the arithmetic in the polynomial iterator, and this made
 it easier to think about.
 */
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 struct Cx { re: f64, im: f64 }
 
 impl Cx {
@@ -136,6 +232,20 @@ impl Cx {
         }
     }
     
+    fn sub(&self, other: &Cx) -> Cx {
+        Cx {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+    
+    /// `1/self`, or (real) infinity for zero.
+    fn recip(&self) -> Cx {
+        let sqm = self.sqmod();
+        if sqm == 0.0 { return Cx { re: f64::INFINITY, im: 0.0 }; }
+        Cx { re: self.re / sqm, im: -self.im / sqm }
+    }
+    
     fn sqmod(&self) -> f64 { (self.re * self.re) + (self.im * self.im) }
 }
 
@@ -149,6 +259,228 @@ static mut COEFFS: [Cx; MAX_COEFFS] = [Cx { re: 0.0, im: 0.0 }; MAX_COEFFS ];
 /// Number of coefficients currently in use by the polynomial iterator.
 static mut N_COEFFS: usize = 1;
 
+/**
+Which quantity of a non-diverging point's orbit gets written to `INTERIOR`
+(and thus picks its interior color).
+*/
+#[derive(Clone, Copy, PartialEq)]
+enum InteriorMetric {
+    /// the smallest modulus the orbit reaches
+    MinModulus,
+    /// the modulus of the last point in the orbit
+    FinalModulus,
+}
+
+/// The interior metric to be used by the next call to `iterate()`.
+static mut INTERIOR_METRIC: InteriorMetric = InteriorMetric::MinModulus;
+
+/**
+What indexes the second axis of `TEXTURE` when coloring diverging points
+with it; `Off` means they get colored from `COLOR_MAP` like always.
+*/
+#[derive(Clone, Copy, PartialEq)]
+enum TextureAxis {
+    Off,
+    /// the angle of the point at which the orbit diverged
+    Angle,
+    /// the smallest modulus the orbit reaches (an orbit trap at 0)
+    Trap,
+}
+
+/// The texture axis to be used by the next call to `iterate()`.
+static mut TEXTURE_AXIS: TextureAxis = TextureAxis::Off;
+
+/**
+The modulus that maps to the far end of the interior color map. Orbits of
+points in the Mandelbrot set never leave the disk of radius 2, so that
+seemed like the obvious choice; anything bigger just gets the last color.
+This is also the range of the orbit trap `TextureAxis`.
+*/
+const INTERIOR_MODULUS_RANGE: f64 = 2.0;
+
+/// Scale a squared modulus to a `u16` according to `INTERIOR_MODULUS_RANGE`.
+fn modulus_value(sqmod: f64) -> u16 {
+    let frac = fmath::sqrt(sqmod) / INTERIOR_MODULUS_RANGE;
+    if frac >= 1.0 { u16::MAX } else { (frac * 65536.0) as u16 }
+}
+
+/**
+Scale the angle of `z` to a `u16`, with a full turn being 65,536. Angles
+below the real axis are done as their conjugates' and then negated, so a
+conjugate's value is always exactly the negation (see the `symmetry`
+module).
+*/
+fn angle_value(z: &Cx) -> u16 {
+    let turns = fmath::atan2(z.im, z.re) / (2.0 * core::f64::consts::PI);
+    let value = (turns.abs() * 65536.0) as u32 as u16;
+    if turns < 0.0 { value.wrapping_neg() } else { value }
+}
+
+/**
+The fractional part of the smoothed iteration count of a point whose orbit
+has just exceeded `sq_mod_limit` by reaching `sqmod`, scaled to a `u16`.
+`ln_degree` is the natural log of the degree of the iterated polynomial;
+for degree 1 (or less) there's no sensible answer, so it's zero.
+*/
+fn smooth_fraction(sqmod: f64, sq_mod_limit: f64, ln_degree: f64) -> u16 {
+    if ln_degree <= 0.0 { return 0; }
+    let ratio = fmath::ln(sqmod) / fmath::ln(sq_mod_limit);
+    let frac = 1.0 - fmath::ln(ratio) / ln_degree;
+    if frac.is_nan() || frac <= 0.0 { 0 }
+    else if frac >= 1.0 { u16::MAX }
+    else { (frac * 65536.0) as u16 }
+}
+
+/**
+Where a point's orbit had got to when the iterator stopped: its last value
+`z` and the smallest squared modulus it had reached along the way. Kept in
+`STATE` for points that didn't diverge, so a longer color map can pick up
+where the last one left off (see `reiterate()`).
+*/
+#[derive(Clone, Copy)]
+struct OrbitState { z: Cx, min_sqmod: f64 }
+
+impl OrbitState {
+    /// An orbit starting from `z` that hasn't gone anywhere yet.
+    const fn start(z: Cx) -> OrbitState {
+        OrbitState { z, min_sqmod: f64::INFINITY }
+    }
+}
+
+/**
+What an iterator found out about a single point: the number of iterations
+`n` it took to diverge, and either its scaled `interior` metric and orbit
+`state` (if it didn't), or its `ORBIT` data (if it did, and `TEXTURE` is in
+use).
+*/
+#[derive(Clone, Copy)]
+struct IterResult { n: u16, interior: u16, orbit: u32, state: OrbitState }
+
+impl IterResult {
+    fn escaped(
+        n: u16,
+        z: &Cx,
+        min_sqmod: f64,
+        sq_mod_limit: f64,
+        ln_degree: f64,
+        axis: TextureAxis,
+    ) -> IterResult {
+        let v = match axis {
+            TextureAxis::Off   => { return IterResult::escaped_plain(n); },
+            TextureAxis::Angle => angle_value(z),
+            TextureAxis::Trap  => modulus_value(min_sqmod),
+        };
+        let frac = smooth_fraction(z.sqmod(), sq_mod_limit, ln_degree);
+        let mut res = IterResult::escaped_plain(n);
+        res.orbit = (frac as u32) | ((v as u32) << 16);
+        res
+    }
+
+    /// A point that diverged after `n` iterations, with no `ORBIT` data.
+    const fn escaped_plain(n: u16) -> IterResult {
+        IterResult {
+            n, interior: 0, orbit: 0,
+            state: OrbitState::start(Cx { re: 0.0, im: 0.0 }),
+        }
+    }
+
+    fn captive(
+        iter_limit: u16,
+        metric: InteriorMetric,
+        min_sqmod: f64,
+        z: &Cx,
+    ) -> IterResult {
+        let sqmod = match metric {
+            InteriorMetric::MinModulus   => min_sqmod,
+            InteriorMetric::FinalModulus => z.sqmod(),
+        };
+        IterResult {
+            n: iter_limit, interior: modulus_value(sqmod), orbit: 0,
+            state: OrbitState { z: *z, min_sqmod },
+        }
+    }
+}
+
+/**
+What every iterator needs to know besides the point (and the polynomial):
+the squared modulus past which an orbit has diverged, the number of
+iterations to give up after (the length of the color map), and what to
+record about the orbits that do and don't diverge.
+*/
+#[derive(Clone, Copy)]
+struct Limits {
+    sq_mod_limit: f64,
+    iter_limit: u16,
+    metric: InteriorMetric,
+    axis: TextureAxis,
+}
+
+/**
+The polynomial the polynomial iterator iterates: its `coeffs` (those of
+the image, in `DrawParams`), its `degree` (one less than the number of
+coefficients to use), and the natural log of that, for smoothing.
+*/
+#[derive(Clone, Copy)]
+struct Poly<'a> {
+    coeffs: &'a [Cx; MAX_COEFFS],
+    degree: usize,
+    ln_degree: f64,
+}
+
+impl Poly<'_> {
+    /// The polynomial with the first `n_coeffs` of `coeffs`, if there are any.
+    fn new(coeffs: &[Cx; MAX_COEFFS], n_coeffs: usize) -> Option<Poly<'_>> {
+        // Limit number of polynomial terms to sane amount.
+        let degree = if n_coeffs < 1 { return None; }
+                else if n_coeffs > MAX_COEFFS { MAX_COEFFS-1 }
+                else { n_coeffs-1 };
+        Some(Poly { coeffs, degree, ln_degree: ln_degree(degree) })
+    }
+}
+
+/**
+`&mut`s to the buffers the iterators fill in for each pixel: `ITERMAP`,
+`INTERIOR`, `ORBIT`, and `STATE`, all starting at the same pixel.
+*/
+struct Pixels<'a> {
+    itermap: &'a mut [u16],
+    interior: &'a mut [u16],
+    orbit: &'a mut [u32],
+    state: &'a mut [OrbitState],
+}
+
+impl Pixels<'_> {
+    /// Record `res` as the result for pixel `idx`.
+    fn set(&mut self, idx: usize, res: &IterResult) {
+        self.itermap[idx] = res.n;
+        self.interior[idx] = res.interior;
+        self.orbit[idx] = res.orbit;
+        self.state[idx] = res.state;
+    }
+
+    /// The same buffers, for just the pixels from `start` on.
+    fn starting_at(&mut self, start: usize) -> Pixels<'_> {
+        Pixels {
+            itermap: &mut self.itermap[start..],
+            interior: &mut self.interior[start..],
+            orbit: &mut self.orbit[start..],
+            state: &mut self.state[start..],
+        }
+    }
+}
+
+impl bands::Rows for Pixels<'_> {
+    fn split_at(self, n: usize) -> (Self, Self) {
+        let (itermap, itermap_rest) = self.itermap.split_at_mut(n);
+        let (interior, interior_rest) = self.interior.split_at_mut(n);
+        let (orbit, orbit_rest) = self.orbit.split_at_mut(n);
+        let (state, state_rest) = self.state.split_at_mut(n);
+        (Pixels { itermap, interior, orbit, state },
+         Pixels { itermap: itermap_rest, interior: interior_rest,
+                  orbit: orbit_rest, state: state_rest })
+    }
+}
+
 /**
 To make the value of `iterator` field of the `DrawParams` struct below
 impossible to mistake.
@@ -159,6 +491,61 @@ enum IteratorType {
     Polynomial,
 }
 
+/**
+How `iterate()` goes about filling in a whole image.
+*/
+#[derive(Clone, Copy, PartialEq)]
+enum Strategy {
+    /// every pixel, one by one
+    BruteForce,
+    /// Mariani–Silver subdivision (see the `subdivide` module)
+    Subdivide,
+    /// boundary tracing (see the `boundary` module)
+    BoundaryTrace,
+}
+
+/// The strategy to be used by the next call to `redraw()`.
+static mut STRATEGY: Strategy = Strategy::BruteForce;
+
+/**
+What `iterate()` does its arithmetic in. `DrawParams::precision()` picks
+one for each image.
+*/
+#[derive(Clone, Copy, PartialEq)]
+enum Precision {
+    /// `f32`, four pixels at a time (see the `single` module)
+    Single,
+    /// `f64`, two pixels at a time (see the `simd` module)
+    Double,
+    /// double-double, one pixel at a time (see the `dd` module)
+    DoubleDouble,
+    /// `f64` differences from a reference orbit (see the `perturb` module)
+    Perturbation,
+}
+
+/**
+The fewest `f32` ulps (of the biggest coordinate in view) a pixel has to
+span for a Mandelbrot image to be iterated in single precision.
+*/
+const SINGLE_PRECISION_ULPS: f64 = 8192.0;
+
+/// The same for double precision, before switching to double-double.
+const DOUBLE_PRECISION_ULPS: f64 = 256.0;
+
+/// The precision forced on the next call to `redraw()`, if any.
+static mut FORCE_PRECISION: Option<Precision> = None;
+
+/// The corner's coordinates have nothing past double-double (see `DrawParams`).
+const NO_REST: [Fixed; 2] = [Fixed::zero(fixed::MAX_LIMBS); 2];
+
+/// The reference orbits for perturbation (see the `perturb` module).
+static mut REFERENCES: perturb::References = perturb::References::new();
+
+/// `&mut REFERENCES`, made the same way as `buffers()`.
+unsafe fn references() -> &'static mut perturb::References {
+    &mut *core::ptr::addr_of_mut!(REFERENCES)
+}
+
 /**
 Stores components about the size of the image and its coverage of the
 complex plane. This makes the call to `reiterate()` (and thus `recolor()`
@@ -167,6 +554,7 @@ its subfunctions require fewer parameters and thus easier to look at.
 Honestly, it also seemed to reduce binary size when introduced, for some
 reason.
 */
+#[derive(Clone, Copy)]
 struct DrawParams {
     /// width of image in pixels
     xpix: usize,
@@ -176,27 +564,286 @@ struct DrawParams {
     x: f64,
     /// imaginary coordinate of upper-left-hand corner of image
     y: f64,
+    /**
+    The low halves of `x` and `y`, when the corner is given as double-double
+    coordinates (see `redraw_dd()`), and 0 otherwise. Only double-double
+    precision and perturbation can see them.
+    */
+    x_lo: f64,
+    y_lo: f64,
+    /**
+    Whatever's left of the corner's coordinates past `x + x_lo` and
+    `y + y_lo`, when it's given in even more precision than double-double
+    (see `redraw_exact()`), and 0 otherwise. Only perturbation can see it.
+    */
+    x_rest: Fixed,
+    y_rest: Fixed,
     /// width of image on the complex plane
     width: f64,
+    /**
+    When rendering a tile of a bigger image, the width and height of the
+    whole image in pixels, and the column and row of the whole image where
+    the tile starts. `x`, `y`, and `width` are still those of the whole
+    image. For an ordinary image, these are just `xpix`, `ypix`, 0, and 0.
+    */
+    full_xpix: usize,
+    full_ypix: usize,
+    x_off: usize,
+    y_off: usize,
+    /**
+    Number of extra columns (on the right) and rows (on the bottom) that are
+    iterated and colored, but aren't part of the output. Smoothing needs
+    these to get the edges of a tile right; see `render_tile()`.
+    */
+    apron_x: usize,
+    apron_y: usize,
+    /**
+    The matrix `[a, b, c, d]` (that is, `a b` over `c d`) that takes a
+    pixel's offset from the upper-left-hand corner of the (unrotated) image
+    to its offset on the plane, for rotated or skewed views.
+    */
+    matrix: [f64; 4],
+    /// the warp each point goes through after that (see the `warp` module)
+    warp: Warp,
+    /**
+    Size of the square blocks the iterated image is made of: 1 when every
+    pixel has been iterated, bigger partway through a progressive render
+    (see `progressive_step()`), and 0 before anything has been iterated.
+    */
+    block: usize,
+    /**
+    For drawing a bit at a time (see `render_begin()`): whether a render is
+    under way, whether it's `progressive`, and the row the next
+    `render_step()` starts at.
+    */
+    rendering: bool,
+    progressive: bool,
+    cursor: usize,
+    /**
+    The color map lengths as they were when the render began, which every
+    `render_step()` iterates with, so all the rows agree even if the color
+    maps get updated in between.
+    */
+    maps: MapLengths,
     /// total number of shades in the last-used colormap
     colormap_length: usize,
     /// last-used iterator
     iterator: IteratorType,
+    /// the polynomial iterator's coefficients, as they were for this image
+    coeffs: [Cx; MAX_COEFFS],
+    n_coeffs: usize,
     /// smoothing amount
     smooth_frac: Option<f32>,
+    /// how the image gets iterated
+    strategy: Strategy,
+    /**
+    Whether the `INTERIOR` data are right for every point that didn't
+    diverge. Strategies other than brute force make them up when they aren't
+    needed (that is, when there's no interior color map).
+    */
+    interior_exact: bool,
+    /// interior metric used for the current `INTERIOR` data
+    interior_metric: InteriorMetric,
+    /// texture axis used for the current `ORBIT` data
+    texture_axis: TextureAxis,
+    /**
+    The pixels whose data were carried over from the last image (see the
+    `reuse` module), while the rest are still being iterated. Whether the
+    old pixels line up with the new ones is worked out from the exact `x`,
+    `y`, and `width` of both images.
+    */
+    kept: Option<reuse::Kept>,
+    /**
+    How the image mirrors itself, if it's being iterated only halfway (see
+    the `symmetry` module). The other half is `kept` meanwhile.
+    */
+    mirror: Option<symmetry::Mirror>,
+    /// the precision to iterate in regardless of the view (for testing)
+    forced_precision: Option<Precision>,
+}
+
+impl DrawParams {
+    /**
+    The point on the complex plane at column `xp` and row `yp` of the image.
+    Pixel positions are always counted from the corner of the whole image,
+    so tiles line up exactly with each other (and with an image drawn all
+    at once).
+    
+    The pixel's offset from the corner (in plane units, right and up) goes
+    through `matrix` before being added to the corner, so with the identity
+    matrix this is the same plain grid it's always been. Then the point
+    goes through `warp`.
+    */
+    fn point_at(&self, xp: usize, yp: usize) -> Cx {
+        let off = self.offset_at(xp, yp);
+        self.warp.apply(Cx { re: self.x + off.re, im: self.y + off.im })
+    }
+    
+    /// How far the (unwarped) point at column `xp` and row `yp` is from the corner.
+    fn offset_at(&self, xp: usize, yp: usize) -> Cx {
+        let xpixf = self.full_xpix as f64;
+        let ypixf = self.full_ypix as f64;
+        let height = self.width * ypixf / xpixf;
+        let u = self.width * (((self.x_off + xp) as f64) / xpixf);
+        let v = -(height * (((self.y_off + yp) as f64) / ypixf));
+        let [a, b, c, d] = self.matrix;
+        Cx { re: a * u + b * v, im: c * u + d * v }
+    }
+    
+    /**
+    `point_at()` in double-double precision, with the low halves of the
+    corner added in. Warps only work in `f64`, so a warped point is just
+    `point_at()`'s.
+    */
+    fn point_dd(&self, xp: usize, yp: usize) -> dd::DdCx {
+        if self.warp != Warp::Identity { return dd::DdCx::from(&self.point_at(xp, yp)); }
+        let off = self.offset_at(xp, yp);
+        dd::DdCx {
+            re: Dd::new(self.x, self.x_lo).add(Dd::from(off.re)),
+            im: Dd::new(self.y, self.y_lo).add(Dd::from(off.im)),
+        }
+    }
+    
+    /// The (unwarped) point `off` from the corner, in `len`-limb fixed point.
+    fn point_fixed(&self, off: &Cx, len: usize) -> (Fixed, Fixed) {
+        let coord = |hi: f64, lo: f64, rest: &Fixed, off: f64| Fixed::from_f64(hi, len)
+            .add(&Fixed::from_f64(lo, len))
+            .add(&rest.truncated(len))
+            .add(&Fixed::from_f64(off, len));
+        (coord(self.x, self.x_lo, &self.x_rest, off.re),
+         coord(self.y, self.y_lo, &self.y_rest, off.im))
+    }
+    
+    /// The distance between neighboring pixels on the plane.
+    fn spacing(&self) -> f64 {
+        let [a, b, c, d] = self.matrix;
+        self.width / (self.full_xpix as f64) * fmath::sqrt((a * d - b * c).abs())
+    }
+    
+    /**
+    The precision to iterate the image in. Unless one is forced, it's single
+    precision for the Mandelbrot iterator while a pixel spans at least
+    `SINGLE_PRECISION_ULPS` ulps (in `f32`) of the biggest coordinate
+    anywhere in the whole image (or 2, if they're all smaller; orbits get at
+    least that far), double while it spans `DOUBLE_PRECISION_ULPS` ulps in
+    `f64`, and after that, perturbation for the Mandelbrot iterator and
+    double-double for the polynomial one. The polynomial iterator's Julia
+    sets are too chaotic for single precision, so they start at double.
+    Warped views always get double precision, since a pixel can be a lot
+    smaller in some places than others. Perturbation can't be forced on
+    them (or on the polynomial iterator); they get double-double instead.
+    */
+    fn precision(&self) -> Precision {
+        let mandelbrot = matches!(self.iterator, IteratorType::Mandelbrot);
+        if let Some(p) = self.forced_precision {
+            if p == Precision::Perturbation && (!mandelbrot || self.warp != Warp::Identity) {
+                return Precision::DoubleDouble;
+            }
+            return p;
+        }
+        if self.warp != Warp::Identity { return Precision::Double; }
+        let height = self.width * (self.full_ypix as f64) / (self.full_xpix as f64);
+        let [a, b, c, d] = self.matrix;
+        let spacing = self.spacing();
+        let mut biggest: f64 = 2.0;
+        for (u, v) in [(0.0, 0.0), (self.width, 0.0), (0.0, -height), (self.width, -height)] {
+            biggest = biggest.max((self.x + (a * u + b * v)).abs())
+                .max((self.y + (c * u + d * v)).abs());
+        }
+        if mandelbrot && spacing >= SINGLE_PRECISION_ULPS * (f32::EPSILON as f64) * biggest {
+            Precision::Single
+        } else if spacing >= DOUBLE_PRECISION_ULPS * f64::EPSILON * biggest {
+            Precision::Double
+        } else if mandelbrot {
+            Precision::Perturbation
+        } else {
+            Precision::DoubleDouble
+        }
+    }
+
+    /// The iterators' `Limits` for this image, with a color map `map_length` long.
+    fn limits(&self, map_length: usize) -> Limits {
+        Limits {
+            sq_mod_limit: SQ_MOD_LIMIT,
+            iter_limit: map_length as u16,
+            metric: self.interior_metric,
+            axis: self.texture_axis,
+        }
+    }
+}
+
+/// The 2 x 2 identity matrix, as `[a, b, c, d]` (see `DrawParams`).
+const IDENTITY: [f64; 4] = [1.0, 0.0, 0.0, 1.0];
+
+/// The view matrix to be used by the next call to `redraw()`.
+static mut VIEW_MATRIX: [f64; 4] = IDENTITY;
+
+/// The warp to be used by the next call to `redraw()`.
+static mut WARP: Warp = Warp::Identity;
+
+/**
+Which pixels of the image a pass of `iterate()` does: those in every
+`step`th row and column. Unless it's the `first` pass, the pixels in every
+`2 * step`th row and column have already been done by the pass before, so
+they're skipped.
+*/
+#[derive(Clone, Copy)]
+struct Grid { step: usize, first: bool }
+
+impl Grid {
+    /// every single pixel
+    const ALL: Grid = Grid { step: 1, first: true };
+    
+    /// The first column and the column spacing of the pixels to do in row
+    /// `yp`, if there are any.
+    fn columns(&self, yp: usize) -> Option<(usize, usize)> {
+        if !yp.is_multiple_of(self.step) { None }
+        else if !self.first && yp.is_multiple_of(2 * self.step) {
+            Some((self.step, 2 * self.step))
+        } else {
+            Some((0, self.step))
+        }
+    }
 }
 
+/// Spacing of the pixels done by the first pass of a progressive render.
+const COARSEST_STEP: usize = 8;
+
 /**
 Default `DrawParams`, really only here because `static`s require initial
-values. They get overwritten before they're needed.
+values. They get overwritten before they're needed (the tests start from
+them, too).
 */
-static mut DRAW_PARAMS: DrawParams = DrawParams {
-    xpix: 1200, ypix: 800, x: -2.0, y: 1.0, width: 3.0,
+const DEFAULT_DRAW_PARAMS: DrawParams = DrawParams {
+    xpix: 1200, ypix: 800, x: -2.0, y: 1.0, x_lo: 0.0, y_lo: 0.0, width: 3.0,
+    x_rest: NO_REST[0], y_rest: NO_REST[1],
+    full_xpix: 1200, full_ypix: 800, x_off: 0, y_off: 0,
+    apron_x: 0, apron_y: 0,
+    matrix: IDENTITY,
+    warp: Warp::Identity,
+    block: 0,
+    rendering: false, progressive: false, cursor: 0,
+    maps: MapLengths { colors: 128, interior: 0 },
     colormap_length: 128,
     iterator: IteratorType::Mandelbrot,
+    coeffs: [Cx { re: 0.0, im: 0.0 }; MAX_COEFFS], n_coeffs: 1,
     smooth_frac: None,
+    strategy: Strategy::BruteForce,
+    interior_exact: true,
+    interior_metric: InteriorMetric::MinModulus,
+    texture_axis: TextureAxis::Off,
+    kept: None,
+    mirror: None,
+    forced_precision: None,
 };
 
+static mut DRAW_PARAMS: DrawParams = DEFAULT_DRAW_PARAMS;
+
+/// `&mut DRAW_PARAMS`, made the same way as `buffers()`.
+unsafe fn draw_params() -> &'static mut DrawParams {
+    &mut *core::ptr::addr_of_mut!(DRAW_PARAMS)
+}
+
 /**
 Populate the `COLOR_MAP` based on color gradient data.
 
@@ -255,6 +902,11 @@ Exported function to set the values for gradient `n`. Takes `n` followed
 by the RGB values (in that order) of the beginning color, then the end
 color, then finally the number of shades it should take to fade between
 the two.
+
+# Safety
+
+Writes gradient `n` of `R0` and the rest, which are `static mut`s, like
+nearly everything here; see the crate docs.
 */
 #[no_mangle]
 pub unsafe extern fn set_gradient(
@@ -275,6 +927,10 @@ pub unsafe extern fn set_gradient(
 Exported function to set the number of gradients in the current color map.
 Without this value, `make_color_map()` has no idea how many of the gradient
 steps to use.
+
+# Safety
+
+Writes `N_GRADIENTS`; see the crate docs.
 */
 #[no_mangle]
 pub unsafe fn set_n_gradients(n: usize) { 
@@ -284,6 +940,10 @@ pub unsafe fn set_n_gradients(n: usize) {
 /**
 Exported function to set the "default" color, the color a pixel will get
 if iterating its point exhausts the color map.
+
+# Safety
+
+Writes `DEFAULT_COLOR`; see the crate docs.
 */
 #[no_mangle]
 pub unsafe fn set_default(r: u8, g: u8, b: u8) {
@@ -295,6 +955,10 @@ pub unsafe fn set_default(r: u8, g: u8, b: u8) {
 /**
 Exported function to recalculate/repopulate the `COLOR_MAP`, presumably
 after calling `set_gradient()` and `set_n_gradients()`.
+
+# Safety
+
+Reads the gradients and writes `COLOR_MAP`; see the crate docs.
 */
 #[no_mangle]
 pub unsafe extern fn update_color_map() {
@@ -308,200 +972,837 @@ pub unsafe extern fn update_color_map() {
 }
 
 /**
-Walk the iteration data in `ITERMAP` and use the color data in `COLOR_MAP`
-to write the actual image data to the `IMAGE` buffer.
+Exported function to set the values for interior gradient `n`. Arguments
+are the same as for `set_gradient()`.
+
+# Safety
 
-The first two arguements are immutable references to `ITERMAP` and
-`COLOR_MAP`, `outbuff` is an `&mut` to `IMAGE`, `default_color` should be
-self-explanatory, `npix` is the total number of pixels in the image
-(that is, the length of the meaningful data in `ITERMAP`), and `n_shades`
-is the value of `CURRENT_COLORMAP_LENGTH` (that is, the length of the
-meaningful data in `COLOR_MAP`).
+Writes gradient `n` of `IR0` and the rest; see the crate docs.
 */
-fn color_itermap(
-    itrmap: &[u16; IMAGE_SIZE],
-    colormap: &[u32; COLOR_MAP_LENGTH],
-    outbuff: &mut [u32; IMAGE_SIZE],
-    default_color: u32,
-    npix: usize,
-    n_shades: usize,
+#[no_mangle]
+pub unsafe extern fn set_interior_gradient(
+    n: usize,
+    r0: u8, g0: u8, b0: u8,
+    r1: u8, g1: u8, b1: u8,
+    shades: u16
 ) {
-    for n in 0..npix {
-        let col_idx = itrmap[n] as usize;
-        if col_idx < n_shades {
-            outbuff[n] = colormap[col_idx];
-        } else {
-            outbuff[n] = default_color;
-        }
+    if n < MAX_GRADIENTS {
+        IR0[n] = r0; IR1[n] = r1;
+        IG0[n] = g0; IG1[n] = g1;
+        IB0[n] = b0; IB1[n] = b1;
+        ISHADES[n] = shades;
     }
 }
 
 /**
-Exported function to set coefficients for the polynomial iterator.
+Exported function to set the number of gradients in the interior color map.
+Set this to zero to go back to coloring the interior with `DEFAULT_COLOR`.
+
+# Safety
+
+Writes `N_INTERIOR_GRADIENTS`; see the crate docs.
 */
 #[no_mangle]
-pub unsafe extern fn set_coeff(n: usize, re: f64, im: f64) {
-    if n < MAX_COEFFS {
-        COEFFS[n] = Cx{ re, im };
-    }
+pub unsafe extern fn set_n_interior_gradients(n: usize) {
+    if n < MAX_GRADIENTS { N_INTERIOR_GRADIENTS = n; }
 }
 
 /**
-Exported function to set the number of coefficients for the polynomial
-iterator to use.
+Exported function to recalculate/repopulate the `INTERIOR_MAP`, presumably
+after calling `set_interior_gradient()` and `set_n_interior_gradients()`.
+
+# Safety
+
+Reads the interior gradients and writes `INTERIOR_MAP`; see the crate docs.
 */
 #[no_mangle]
-pub unsafe extern fn set_n_coeffs(n: usize) {
-    if n < MAX_COEFFS { N_COEFFS = n; }
+pub unsafe extern fn update_interior_map() {
+    make_color_map(
+        &IR0, &IR1, &IG0, &IG1, &IB0, &IB1,
+        &ISHADES, N_INTERIOR_GRADIENTS,
+        &mut INTERIOR_MAP, &mut CURRENT_INTERIOR_MAP_LENGTH
+    );
 }
 
 /**
-Return how many iterations of z = z^2 + c the point `x` + i`y` takes before its
-squared modulus exceeds `sq_mod_limit` (or `iter_limit`, if it doesn't
-exceed it by `iter_limit` iterations). `iter_limit` should be the length
-of the valid data in `COLOR_MAP`.
+Exported function to choose what non-diverging points are shaded by: if
+`use_final_modulus` is `true`, the modulus of the last point in the orbit;
+otherwise the smallest modulus the orbit reaches. This takes effect on the
+next call to `redraw()` or `recolor()`.
+
+# Safety
+
+Writes `INTERIOR_METRIC`; see the crate docs.
 */
-fn mandelbrot_iter(
-    x: f64, y: f64,
-    sq_mod_limit: f64, iter_limit: u16
-) -> u16 {
-    let c = Cx { re: x, im: y };
-    let mut cur = Cx { re: 0.0, im: 0.0 };
-    
-    for n in 0..iter_limit {
-        cur = c.add(&cur.mul(&cur));
-        if cur.sqmod() > sq_mod_limit { return n; }
-    }
-    return  iter_limit;
+#[no_mangle]
+pub unsafe extern fn set_interior_metric(use_final_modulus: bool) {
+    INTERIOR_METRIC = match use_final_modulus {
+        true  => InteriorMetric::FinalModulus,
+        false => InteriorMetric::MinModulus,
+    };
 }
 
 /**
-Given the image and complex plane coverage parameters in `dp`, fill the
-appropriate amount of `ITERMAP` (passed as `&mut buff`) with iteration data.
-
-`map_length` is the length of the data in `COLOR_MAP` (that is, the value
-of `CURRENT_COLORMAP_LENGTH`).
+Set the gradients of the color map (or of the interior color map, if
+`interior` is `true`) to go from each of `stops` to the next, and from the
+last back around to the first, in the corresponding number of `shades`.
+The number of gradients is set, too, but the color map still needs
+updating.
 */
-fn calc_mbrot_itermap(
-    dp: &DrawParams,
-    buff: &mut [u16; IMAGE_SIZE],
-    map_length: usize,
-) {
-    let xpixf = dp.xpix as f64;
-    let ypixf = dp.ypix as f64;
-    let height = dp.width * ypixf / xpixf;
-    
-    let n_shades = map_length as u16;
-    
-    for yp in 0..dp.ypix {
-        let y_val = dp.y - height * ((yp as f64) / ypixf);
-        let idx_base: usize = yp * dp.xpix;
-        for xp in 0..dp.xpix {
-            let x_val = dp.x + dp.width * ((xp as f64) / xpixf);
-            let idx = idx_base + xp;
-            let n = mandelbrot_iter(x_val, y_val, SQ_MOD_LIMIT, n_shades);
-            buff[idx] = n;
+unsafe fn load_stops(stops: &[Rgb], shades: &[u16], interior: bool) {
+    let n = stops.len();
+    for i in 0..n {
+        let (a, b) = (stops[i], stops[(i + 1) % n]);
+        if interior {
+            set_interior_gradient(i, a.r, a.g, a.b, b.r, b.g, b.b, shades[i]);
+        } else {
+            set_gradient(i, a.r, a.g, a.b, b.r, b.g, b.b, shades[i]);
         }
     }
+    if interior { set_n_interior_gradients(n); }
+    else { set_n_gradients(n); }
 }
 
 /**
-Like `mandlebrot_iter()`, above, it determines how many iterations of the
-polynomial iterator (whose coefficients are given by `coeffs`) it takes for
-the given point's squared modulus to exceed `sq_mod_limit`.
-
-The extra two arguments in there are a reference to `COEFFS` (`coeffs`) and
-the degree of the polynomial (`degree`, which is one less than the number
-of coefficients to use).
+`shades` per gradient, cut down if need be so `n` gradients of them fit in
+the color map with room to spare: its length has to fit in a `u16`, since
+that's the iteration limit.
 */
-fn polynomial_iter(
-    x: f64, y: f64,
-    coeffs: &[Cx; MAX_COEFFS],
-    degree: usize,
-    sq_mod_limit: f64,
-    iter_limit: u16
-) -> u16 {
-    let mut cur = Cx { re: x, im: y };
-    
-    for n in 0..iter_limit {
-        let mut new = Cx { re: 0.0, im: 0.0 };
-        let mut z   = Cx { re: 1.0, im: 0.0 };
-        for m in 0..degree {
-            let t = z.mul(&coeffs[m]);
-            new = new.add(&t);
-            z = z.mul(&cur);
-        }
-        let t = z.mul(&coeffs[degree]);
-        cur = new.add(&t);
-        if cur.sqmod() > sq_mod_limit { return n; }
-    }
-    return iter_limit
+fn clamp_shades(shades: u16, n: usize) -> u16 {
+    let max_shades = (COLOR_MAP_LENGTH - 1) / n;
+    if shades as usize > max_shades { max_shades as u16 } else { shades }
 }
 
 /**
-Like `calc_mbrot_itermap()`, above, but uses the polynomial iterator. The
-two extra arguments at the end specify the polynomial:
+Exported function to fill the gradients with a procedurally-generated
+palette. The same `seed` and `style` always give the same palette.
+  * `style`: 0 for a cosine palette, 1 for analogous hues, 2 for
+    complementary hues, 3 for a monochrome ramp
+  * `n_gradients`: how many gradients (that is, how many distinct colors)
+    the palette should have
+  * `shades`: the number of steps in each gradient
+  * `interior`: if `true`, the interior gradients are filled instead
+
+As with `set_gradient()`, follow up with `update_color_map()` (or
+`update_interior_map()`) to actually use the new palette.
 
-  * `coeffs` should be a references to `COEFFS`
-  * `n_coeffs` should be the values of `N_COEFFS`
+# Safety
+
+Writes the gradients (or the interior ones); see the crate docs.
 */
-fn calc_poly_itermap(
-    dp: &DrawParams,
-    buff: &mut [u16; IMAGE_SIZE],
-    map_length: usize,
-    coeffs: &[Cx; MAX_COEFFS],
-    n_coeffs: usize
+#[no_mangle]
+pub unsafe extern fn random_palette(
+    seed: u64,
+    style: u8,
+    n_gradients: usize,
+    shades: u16,
+    interior: bool,
 ) {
-    // Limit number of polynomial terms to sane amount.
-    let degree = if n_coeffs < 1 { return; }    // Stop; this is stupid.
-            else if n_coeffs > MAX_COEFFS { MAX_COEFFS-1 }
-            else { n_coeffs-1 };
+    let n = if n_gradients < 1 { 1 }
+            else if n_gradients >= MAX_GRADIENTS { MAX_GRADIENTS - 1 }
+            else { n_gradients };
+    let shades = clamp_shades(shades, n);
     
-    let xpixf = dp.xpix as f64;
-    let ypixf = dp.ypix as f64;
-    let height = dp.width * ypixf / xpixf;
-    
-    let n_shades = map_length as u16;
-    
-    for yp in 0..dp.ypix {
-        let y_val = dp.y - height * ((yp as f64) / ypixf);
-        let idx_base: usize = yp * dp.xpix;
-        for xp in 0..dp.xpix {
-            let x_val = dp.x + dp.width * ((xp as f64) / xpixf);
-            let idx = idx_base + xp;
-            let n = polynomial_iter(
-                x_val, y_val,
-                coeffs,
-                degree,
-                SQ_MOD_LIMIT, n_shades
-            );
-            buff[idx] = n;
-        }
-    }
+    let mut stops = [Rgb::BLACK; MAX_GRADIENTS];
+    palette::generate(seed, PaletteStyle::from_u8(style), &mut stops[..n]);
+    load_stops(&stops[..n], &[shades; MAX_GRADIENTS][..n], interior);
 }
 
+/// Exported function to get the number of built-in palettes.
+#[no_mangle]
+pub extern fn n_presets() -> usize { palette::PRESETS.len() }
+
 /**
-Given the provided `DrawParams`, the current color `map_length`, and
-references to the polynomial coefficients `coeff` (and their number,
-`n_coeff`), pick the proper iterator and fill the appropriate amount of
-`ITERMAP` (passed `&mut` as `itermap`) with iteration data.
+Exported function to get a pointer to the name of built-in palette `n`, as
+UTF-8 bytes in module memory; `preset_name_len()` gives its length. Returns
+a null pointer if there's no such palette.
 */
-fn iterate(
-    dp: &mut DrawParams,
-    itermap: &mut [u16; IMAGE_SIZE],
-    map_length: usize,
-    coeffs: &[Cx; MAX_COEFFS],
-    n_coeffs: usize,
+#[no_mangle]
+pub extern fn preset_name(n: usize) -> *const u8 {
+    match palette::PRESETS.get(n) {
+        Some(p) => p.name.as_ptr(),
+        None => core::ptr::null(),
+    }
+}
+
+/// Exported function to get the length (in bytes) of the name of preset `n`.
+#[no_mangle]
+pub extern fn preset_name_len(n: usize) -> usize {
+    match palette::PRESETS.get(n) {
+        Some(p) => p.name.len(),
+        None => 0,
+    }
+}
+
+/// Exported function to get the number of gradients in preset `n`.
+#[no_mangle]
+pub extern fn preset_len(n: usize) -> usize {
+    match palette::PRESETS.get(n) {
+        Some(p) => p.stops.len(),
+        None => 0,
+    }
+}
+
+/**
+Exported function to get the starting color of gradient `i` of preset `n`
+as `0xRRGGBB` (so front ends can show it), or zero if there's no such thing.
+*/
+#[no_mangle]
+pub extern fn preset_color(n: usize, i: usize) -> u32 {
+    match palette::PRESETS.get(n).and_then(|p| p.stops.get(i)) {
+        Some(s) => ((s.color.r as u32) << 16) | ((s.color.g as u32) << 8)
+                    | (s.color.b as u32),
+        None => 0,
+    }
+}
+
+/// Exported function to get the number of shades in gradient `i` of preset `n`.
+#[no_mangle]
+pub extern fn preset_shades(n: usize, i: usize) -> u16 {
+    match palette::PRESETS.get(n).and_then(|p| p.stops.get(i)) {
+        Some(s) => s.shades,
+        None => 0,
+    }
+}
+
+/**
+Exported function to make a palette out of a reference image. Write the
+image's RGBA data (as from a canvas's `.getImageData()`) into the `IMAGE`
+buffer first; `n_pixels` is the number of pixels written. Its most
+representative `n_colors` colors become the gradients (or the interior
+gradients, if `interior` is `true`), each `shades` long, ordered so that
+each color fades into a similar one.
+
+Returns the number of gradients actually set, which can be fewer than
+`n_colors` for images with few distinct colors, or zero (and nothing gets
+changed) if the image is empty or fully transparent. As usual, the color map
+needs updating afterward; then `recolor()` puts the fractal back in `IMAGE`.
+
+If the reference image is bigger than the current one, make room for it
+first with `reserve_image()`.
+
+# Safety
+
+Reads `IMAGE` and writes the gradients (or the interior ones); see the
+crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn extract_palette(
+    n_pixels: usize,
+    n_colors: usize,
+    shades: u16,
+    interior: bool,
+) -> usize {
+    let cap = buffers().capacity();
+    let npix = if n_pixels > cap { cap } else { n_pixels };
+    let n = if n_colors < 1 { 1 }
+            else if n_colors >= MAX_GRADIENTS { MAX_GRADIENTS - 1 }
+            else { n_colors };
+    let shades = clamp_shades(shades, n);
+    
+    let mut stops = [Rgb::BLACK; MAX_GRADIENTS];
+    let found = palette::extract(&buffers().image()[..npix], &mut stops[..n]);
+    if found > 0 {
+        load_stops(&stops[..found], &[shades; MAX_GRADIENTS][..found], interior);
+    }
+    found
+}
+
+/**
+Exported function to set the gradients (or the interior gradients, if
+`interior` is `true`) to built-in palette `n` in one go. As with
+`random_palette()`, the color map still needs updating afterward. Does
+nothing if there's no such palette.
+
+# Safety
+
+Writes the gradients (or the interior ones); see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn load_preset(n: usize, interior: bool) {
+    let preset = match palette::PRESETS.get(n) {
+        Some(p) => p,
+        None => { return; },
+    };
+    let len = preset.stops.len();
+    let mut stops = [Rgb::BLACK; MAX_GRADIENTS];
+    let mut shades = [0u16; MAX_GRADIENTS];
+    for (i, s) in preset.stops.iter().enumerate() {
+        stops[i] = s.color;
+        shades[i] = s.shades;
+    }
+    load_stops(&stops[..len], &shades[..len], interior);
+}
+
+/**
+Exported function to set the dimensions of the 2D palette in `TEXTURE`,
+and `period`, the number of iterations it takes to go across it once.
+Dimensions that don't fit in `TEXTURE` (and non-positive periods) are
+ignored.
+
+# Safety
+
+Writes the texture's shape, which `TEXTURE` has to have room for; see
+the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_texture(width: usize, height: usize, period: f32) {
+    if width > 0 && height > 0 && width * height <= TEXTURE_SIZE {
+        TEXTURE_WIDTH = width;
+        TEXTURE_HEIGHT = height;
+    }
+    if period > 0.0 { TEXTURE_PERIOD = period; }
+}
+
+/**
+Exported function to color diverging points from `TEXTURE` instead of
+`COLOR_MAP`. `axis` picks what indexes its second dimension:
+  * 0: nothing; go back to using `COLOR_MAP`
+  * 1: the angle at which the point's orbit diverged
+  * 2: the smallest modulus the orbit reaches
+
+This takes effect on the next call to `redraw()` or `recolor()`, but as
+the latter has to re-iterate the whole image to switch axes, it won't be
+any faster than the former.
+
+# Safety
+
+Writes `TEXTURE_AXIS`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_texture_axis(axis: u8) {
+    TEXTURE_AXIS = match axis {
+        1 => TextureAxis::Angle,
+        2 => TextureAxis::Trap,
+        _ => TextureAxis::Off,
+    };
+}
+
+/**
+Everything `color_itermap()` needs to color diverging points from a 2D
+palette: references to `TEXTURE` and `ORBIT`, and the texture's shape.
+*/
+struct Texture<'a> {
+    texels: &'a [u32; TEXTURE_SIZE],
+    orbit: &'a [u32],
+    width: usize,
+    height: usize,
+    period: f32,
+}
+
+impl Texture<'_> {
+    /// The same texture, for just the pixels from `start` on.
+    fn starting_at(&self, start: usize) -> Texture<'_> {
+        Texture {
+            texels: self.texels,
+            orbit: &self.orbit[start..],
+            width: self.width,
+            height: self.height,
+            period: self.period,
+        }
+    }
+    
+    /// The color for pixel `idx`, which diverged after `n` iterations.
+    fn lookup(&self, idx: usize, n: usize) -> u32 {
+        let orbit = self.orbit[idx];
+        let frac = ((orbit & 0xFFFF) as f32) / 65536.0;
+        let t = ((n as f32) + frac) / self.period;
+        let t = t - ((t as u32) as f32);
+        let tx = (t * (self.width as f32)) as usize;
+        let tx = if tx >= self.width { self.width - 1 } else { tx };
+        let ty = (((orbit >> 16) as usize) * self.height) >> 16;
+        self.texels[ty * self.width + tx]
+    }
+}
+
+/**
+The colors `color_itermap()` colors pixels with: references to `COLOR_MAP`
+and `INTERIOR_MAP`, the lengths of the meaningful data in them
+(`CURRENT_COLORMAP_LENGTH` and `CURRENT_INTERIOR_MAP_LENGTH`), and
+`DEFAULT_COLOR`.
+*/
+struct Colors<'a> {
+    colormap: &'a [u32; COLOR_MAP_LENGTH],
+    n_shades: usize,
+    interior_map: &'a [u32; COLOR_MAP_LENGTH],
+    n_interior_shades: usize,
+    default_color: u32,
+}
+
+/**
+Walk the iteration data in `ITERMAP` and use the color data in `COLOR_MAP`
+to write the actual image data to the `IMAGE` buffer.
+
+`itrmap` and `interior` are immutable references to `ITERMAP` and
+`INTERIOR`, and `outbuff` is an `&mut` to `IMAGE`; all of `outbuff` gets
+colored. Points that diverged get the color from `colors.colormap` for
+their iteration count, or from `texture` instead, if there is one.
+
+Points that didn't diverge are colored from `colors.interior_map`
+according to their `interior` values, unless there's no interior color
+map (`colors.n_interior_shades` is zero); then they just get
+`colors.default_color`.
+*/
+fn color_itermap(
+    itrmap: &[u16],
+    interior: &[u16],
+    outbuff: &mut [u32],
+    colors: &Colors,
+    texture: Option<&Texture>,
 ) {
-    let itertype = dp.iterator;
+    let n_interior_shades = colors.n_interior_shades;
+    for n in 0..outbuff.len() {
+        let col_idx = itrmap[n] as usize;
+        if col_idx < colors.n_shades {
+            outbuff[n] = match texture {
+                Some(t) => t.lookup(n, col_idx),
+                None => colors.colormap[col_idx],
+            };
+        } else if n_interior_shades > 0 {
+            let int_idx = ((interior[n] as usize) * n_interior_shades) >> 16;
+            outbuff[n] = colors.interior_map[int_idx];
+        } else {
+            outbuff[n] = colors.default_color;
+        }
+    }
+}
+
+/**
+Exported function to set coefficients for the polynomial iterator.
+
+# Safety
+
+Writes `COEFFS`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_coeff(n: usize, re: f64, im: f64) {
+    if n < MAX_COEFFS {
+        COEFFS[n] = Cx{ re, im };
+    }
+}
+
+/**
+Exported function to set the number of coefficients for the polynomial
+iterator to use.
+
+# Safety
+
+Writes `N_COEFFS`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_n_coeffs(n: usize) {
+    if n < MAX_COEFFS { N_COEFFS = n; }
+}
+
+/**
+Return how many iterations of z = z^2 + c the point `x` + i`y` takes before its
+squared modulus exceeds `limits.sq_mod_limit` (or `limits.iter_limit`, if it
+doesn't exceed it by then). The iteration limit should be the length of the
+valid data in `COLOR_MAP`. If the point doesn't diverge, the result also
+carries its interior value according to `limits.metric`.
+*/
+fn mandelbrot_iter(x: f64, y: f64, limits: &Limits) -> IterResult {
+    let start = OrbitState::start(Cx { re: 0.0, im: 0.0 });
+    mandelbrot_iter_from(x, y, 0, &start, limits)
+}
+
+/**
+`mandelbrot_iter()`, but picking up an orbit that has already had `n0`
+iterations and got to `start`. The result is exactly what it would have been
+all in one go.
+*/
+fn mandelbrot_iter_from(
+    x: f64, y: f64,
+    n0: u16, start: &OrbitState,
+    limits: &Limits,
+) -> IterResult {
+    let c = Cx { re: x, im: y };
+    let mut cur = start.z;
+    let mut min_sqmod = start.min_sqmod;
+    
+    for n in n0..limits.iter_limit {
+        cur = c.add(&cur.mul(&cur));
+        let sqmod = cur.sqmod();
+        if sqmod < min_sqmod { min_sqmod = sqmod; }
+        if sqmod > limits.sq_mod_limit {
+            return IterResult::escaped(
+                n, &cur, min_sqmod, limits.sq_mod_limit,
+                core::f64::consts::LN_2, limits.axis
+            );
+        }
+    }
+    IterResult::captive(limits.iter_limit, limits.metric, min_sqmod, &cur)
+}
+
+/**
+Given the image and complex plane coverage parameters in `dp`, fill the
+appropriate amount of `px` (see `Pixels`) with iteration data, interior
+data, data for texture coloring, and where the orbits that didn't diverge
+got to, iterating with `limits`.
+
+Only the `rows` of the image in that range get done, and the buffers start
+at the first of them (see the `bands` module). Of those, only the pixels
+in `grid` get done.
+*/
+fn calc_mbrot_itermap(
+    dp: &DrawParams,
+    rows: Range<usize>,
+    grid: Grid,
+    mut px: Pixels,
+    limits: &Limits,
+) {
+    for yp in rows.clone() {
+        let (x0, dx) = match grid.columns(yp) {
+            Some(c) => c,
+            None => { continue; },
+        };
+        let idx_base: usize = (yp - rows.start) * dp.xpix;
+        // Two pixels at a time (see the `simd` module), then the odd one.
+        let mut xp = x0;
+        while xp + dx < dp.xpix {
+            let (a, b) = (dp.point_at(xp, yp), dp.point_at(xp + dx, yp));
+            let res = simd::mandelbrot_iter2(&a, &b, limits);
+            for (i, r) in res.iter().enumerate() {
+                px.set(idx_base + xp + i * dx, r);
+            }
+            xp += 2 * dx;
+        }
+        if xp < dp.xpix {
+            let c = dp.point_at(xp, yp);
+            px.set(idx_base + xp, &mandelbrot_iter(c.re, c.im, limits));
+        }
+    }
+}
+
+/**
+Like `mandlebrot_iter()`, above, it determines how many iterations of the
+polynomial iterator (whose coefficients are given by `poly`) it takes for
+the given point's squared modulus to exceed `limits.sq_mod_limit`.
+*/
+fn polynomial_iter(x: f64, y: f64, poly: &Poly, limits: &Limits) -> IterResult {
+    let start = OrbitState::start(Cx { re: x, im: y });
+    polynomial_iter_from(0, &start, poly, limits)
+}
+
+/// `polynomial_iter()` picking up where it left off, like `mandelbrot_iter_from()`.
+fn polynomial_iter_from(
+    n0: u16, start: &OrbitState,
+    poly: &Poly,
+    limits: &Limits,
+) -> IterResult {
+    let (coeffs, degree) = (poly.coeffs, poly.degree);
+    let mut cur = start.z;
+    let mut min_sqmod = start.min_sqmod;
+    
+    for n in n0..limits.iter_limit {
+        let mut new = Cx { re: 0.0, im: 0.0 };
+        let mut z   = Cx { re: 1.0, im: 0.0 };
+        for m in 0..degree {
+            let t = z.mul(&coeffs[m]);
+            new = new.add(&t);
+            z = z.mul(&cur);
+        }
+        let t = z.mul(&coeffs[degree]);
+        cur = new.add(&t);
+        let sqmod = cur.sqmod();
+        if sqmod < min_sqmod { min_sqmod = sqmod; }
+        if sqmod > limits.sq_mod_limit {
+            return IterResult::escaped(
+                n, &cur, min_sqmod, limits.sq_mod_limit, poly.ln_degree,
+                limits.axis
+            );
+        }
+    }
+    IterResult::captive(limits.iter_limit, limits.metric, min_sqmod, &cur)
+}
+
+/// The natural log of `degree`, or zero if it's too small to mean anything.
+fn ln_degree(degree: usize) -> f64 {
+    if degree < 2 { 0.0 } else { fmath::ln(degree as f64) }
+}
+
+/**
+Iterates single pixels of the image described by `dp`, with whichever
+iterator it uses, for the strategies that pick and choose which pixels to
+iterate.
+*/
+struct PixelIter<'a> {
+    dp: &'a DrawParams,
+    limits: Limits,
+    poly: Poly<'a>,
+    precision: Precision,
+    /// the reference orbit, in case it's perturbation
+    reference: &'a perturb::Reference,
+}
+
+impl<'a> PixelIter<'a> {
+    /// `None` if it's the polynomial iterator and there is no polynomial.
+    fn new(
+        dp: &'a DrawParams,
+        map_length: usize,
+        reference: &'a perturb::Reference,
+    ) -> Option<PixelIter<'a>> {
+        let poly = match (Poly::new(&dp.coeffs, dp.n_coeffs), dp.iterator) {
+            (Some(poly), _) => poly,
+            // The Mandelbrot iterator never looks at it.
+            (None, IteratorType::Mandelbrot) => Poly::new(&dp.coeffs, 1)?,
+            (None, IteratorType::Polynomial) => return None,
+        };
+        Some(PixelIter {
+            dp,
+            limits: dp.limits(map_length),
+            poly,
+            precision: dp.precision(),
+            reference,
+        })
+    }
+    
+    fn at(&self, xp: usize, yp: usize) -> IterResult {
+        let dp = self.dp;
+        match (self.precision, dp.iterator) {
+            (Precision::Single, IteratorType::Mandelbrot) => {
+                let start = OrbitState::start(Cx { re: 0.0, im: 0.0 });
+                return single::mandelbrot_iter(&dp.point_at(xp, yp), 0, &start, &self.limits);
+            },
+            (Precision::Single, IteratorType::Polynomial) => {
+                let start = OrbitState::start(dp.point_at(xp, yp));
+                return single::polynomial_iter(0, &start, &self.poly, &self.limits);
+            },
+            (Precision::DoubleDouble, IteratorType::Mandelbrot) => {
+                return dd::mandelbrot_iter(&dp.point_dd(xp, yp), &self.limits);
+            },
+            (Precision::DoubleDouble, IteratorType::Polynomial) => {
+                return dd::polynomial_iter(&dp.point_dd(xp, yp), &self.poly, &self.limits);
+            },
+            (Precision::Perturbation, _) => {
+                let r = self.reference;
+                return perturb::mandelbrot_iter(
+                    r, dp.offset_at(xp, yp).sub(&r.offset), &self.limits
+                );
+            },
+            (Precision::Double, _) => {},
+        }
+        let c = dp.point_at(xp, yp);
+        match dp.iterator {
+            IteratorType::Mandelbrot => mandelbrot_iter(c.re, c.im, &self.limits),
+            IteratorType::Polynomial => polynomial_iter(c.re, c.im, &self.poly, &self.limits),
+        }
+    }
+    
+    /// `at()` for two pixels at once (see the `simd` module).
+    fn at2(&self, (xa, ya): (usize, usize), (xb, yb): (usize, usize)) -> [IterResult; 2] {
+        if self.precision != Precision::Double {
+            return [self.at(xa, ya), self.at(xb, yb)];
+        }
+        let dp = self.dp;
+        let (a, b) = (dp.point_at(xa, ya), dp.point_at(xb, yb));
+        match dp.iterator {
+            IteratorType::Mandelbrot => simd::mandelbrot_iter2(&a, &b, &self.limits),
+            IteratorType::Polynomial => simd::polynomial_iter2(&a, &b, &self.poly, &self.limits),
+        }
+    }
     
-    match itertype {
-        IteratorType::Mandelbrot => calc_mbrot_itermap(dp, itermap, map_length),
-        IteratorType::Polynomial => calc_poly_itermap(dp, itermap, map_length,
-                                        coeffs, n_coeffs),
+    /**
+    `at()` for four pixels at once: in one go in single precision, or two
+    at a time otherwise.
+    */
+    fn at4(&self, pixels: [(usize, usize); 4]) -> [IterResult; 4] {
+        if self.precision != Precision::Single {
+            let (a, b) = (self.at2(pixels[0], pixels[1]), self.at2(pixels[2], pixels[3]));
+            return [a[0], a[1], b[0], b[1]];
+        }
+        let dp = self.dp;
+        let c = pixels.map(|(xp, yp)| dp.point_at(xp, yp));
+        match dp.iterator {
+            IteratorType::Mandelbrot => single::mandelbrot_iter4(
+                c, 0, &[OrbitState::start(Cx { re: 0.0, im: 0.0 }); 4], &self.limits
+            ),
+            IteratorType::Polynomial => single::polynomial_iter4(
+                0, &c.map(OrbitState::start), &self.poly, &self.limits
+            ),
+        }
+    }
+}
+
+/**
+Like `calc_mbrot_itermap()`, but with either iterator, in whatever precision
+`it` uses, four pixels at a time (the last few padded out with copies of
+the first). That's for the precisions other than double.
+*/
+fn calc_pixel_itermap(
+    it: &PixelIter,
+    rows: Range<usize>,
+    grid: Grid,
+    mut px: Pixels,
+) {
+    let xpix = it.dp.xpix;
+    for yp in rows.clone() {
+        let (x0, dx) = match grid.columns(yp) {
+            Some(c) => c,
+            None => { continue; },
+        };
+        let idx_base: usize = (yp - rows.start) * xpix;
+        let mut xp = x0;
+        while xp < xpix {
+            let cols: [usize; 4] = core::array::from_fn(|i| {
+                if xp + i * dx < xpix { xp + i * dx } else { xp }
+            });
+            let res = it.at4(cols.map(|x| (x, yp)));
+            for (x, r) in cols.iter().zip(res.iter()) {
+                px.set(idx_base + x, r);
+            }
+            xp += 4 * dx;
+        }
+    }
+}
+
+/**
+Like `calc_mbrot_itermap()`, above, but uses the polynomial iterator, with
+the polynomial `poly`.
+*/
+fn calc_poly_itermap(
+    dp: &DrawParams,
+    rows: Range<usize>,
+    grid: Grid,
+    mut px: Pixels,
+    poly: &Poly,
+    limits: &Limits,
+) {
+    for yp in rows.clone() {
+        let (x0, dx) = match grid.columns(yp) {
+            Some(c) => c,
+            None => { continue; },
+        };
+        let idx_base: usize = (yp - rows.start) * dp.xpix;
+        let mut xp = x0;
+        while xp + dx < dp.xpix {
+            let (a, b) = (dp.point_at(xp, yp), dp.point_at(xp + dx, yp));
+            let res = simd::polynomial_iter2(&a, &b, poly, limits);
+            for (i, r) in res.iter().enumerate() {
+                px.set(idx_base + xp + i * dx, r);
+            }
+            xp += 2 * dx;
+        }
+        if xp < dp.xpix {
+            let c = dp.point_at(xp, yp);
+            px.set(idx_base + xp, &polynomial_iter(c.re, c.im, poly, limits));
+        }
+    }
+}
+
+/// The lengths of the data in `COLOR_MAP` and `INTERIOR_MAP`.
+#[derive(Clone, Copy)]
+struct MapLengths { colors: usize, interior: usize }
+
+impl MapLengths {
+    /// `CURRENT_COLORMAP_LENGTH` and `CURRENT_INTERIOR_MAP_LENGTH`.
+    unsafe fn current() -> MapLengths {
+        MapLengths { colors: CURRENT_COLORMAP_LENGTH, interior: CURRENT_INTERIOR_MAP_LENGTH }
+    }
+}
+
+/**
+Given the provided `DrawParams` and the current color map lengths `maps`,
+pick the proper iterator (with the polynomial in `dp`) and fill the
+appropriate amount of `px` (see `Pixels`) with iteration data, interior
+data, texture coloring data, and the orbit states of points that don't
+diverge.
+
+Only the pixels in `grid` and in the range of `rows` get iterated (which
+is all of them, for `Grid::ALL` and `0..dp.ypix`). Once the last row of a
+grid is done, `dp.block` says how coarse the image is.
+
+If some pixels' data were carried over from the last image (`dp.kept`),
+only the rest get iterated (by the passes with every pixel in their
+`grid`). The same goes for pixels that mirror others (`dp.mirror`), which
+get copied once the last row is done.
+
+When it's the whole image, it gets done with `dp.strategy` (in one go, not
+in bands), which may make up the `INTERIOR` values of points that don't
+diverge if there's no interior color map (`maps.interior` is 0), and may
+use `scratch` (a word per pixel; `IMAGE` will do) as it sees fit.
+
+Whatever the strategy, the arithmetic is done in `dp.precision()`. For
+perturbation, the reference orbit goes in `refs` at the start of the first
+pass, and glitches get fixed once the last pass is done, so that they get
+fixed the same way however the image was iterated.
+*/
+fn iterate(
+    dp: &mut DrawParams,
+    grid: Grid,
+    rows: Range<usize>,
+    mut px: Pixels,
+    scratch: &mut [u32],
+    maps: MapLengths,
+    refs: &mut perturb::References,
+) {
+    let itertype = dp.iterator;
+    let whole = grid.step == 1 && grid.first && rows.start == 0 && rows.end == dp.ypix;
+    let strategy = if whole { dp.strategy } else { Strategy::BruteForce };
+    let captive_fill_ok = maps.interior == 0;
+    if dp.kept.is_none() {
+        dp.interior_exact = strategy == Strategy::BruteForce || !captive_fill_ok;
+    }
+    let perturbation = dp.precision() == Precision::Perturbation;
+    let limits = dp.limits(maps.colors);
+    if perturbation && grid.first && rows.start == 0 {
+        let center = dp.offset_at(dp.xpix / 2, dp.ypix / 2);
+        refs.main.compute(dp, center, &limits);
+    }
+    let shared: &DrawParams = dp;
+    let pixel_iter = PixelIter::new(shared, maps.colors, &refs.main);
+    // These two decide what to fill in by where they cut the image up, so
+    // they get the whole of it, not bands.
+    let filled = match (strategy, &pixel_iter, &shared.kept) {
+        (Strategy::Subdivide, Some(it), None) => {
+            subdivide::fill_rows(it, rows.clone(), px.starting_at(0), captive_fill_ok);
+            true
+        },
+        (Strategy::BoundaryTrace, Some(it), None)
+            if rows.len() * shared.xpix <= boundary::MAX_PIXELS => {
+            boundary::fill_rows(it, rows.clone(), px.starting_at(0), scratch,
+                                captive_fill_ok);
+            true
+        },
+        _ => false,
     };
+    let (first, start) = (rows.start, rows.start * dp.xpix);
+    
+    if !filled {
+        bands::in_bands(dp.xpix, rows.len(), px.starting_at(start), |band, px| {
+            let band = (band.start + first)..(band.end + first);
+            if let (Some(kept), Some(it), 1) = (&shared.kept, &pixel_iter, grid.step) {
+                reuse::fill_rows(it, kept, grid, band, px);
+                return;
+            }
+            if let Some(it) = pixel_iter.as_ref().filter(|it| it.precision != Precision::Double) {
+                calc_pixel_itermap(it, band, grid, px);
+                return;
+            }
+            match (itertype, Poly::new(&shared.coeffs, shared.n_coeffs)) {
+                (IteratorType::Mandelbrot, _) =>
+                    calc_mbrot_itermap(shared, band, grid, px, &limits),
+                (IteratorType::Polynomial, Some(poly)) =>
+                    calc_poly_itermap(shared, band, grid, px, &poly, &limits),
+                (IteratorType::Polynomial, None) => {},
+            }
+        });
+    }
+    if perturbation && rows.end == dp.ypix && grid.step == 1 {
+        perturb::fix_glitches(shared, &mut refs.spare, Grid::ALL, px.starting_at(0), &limits);
+    }
     
-    dp.colormap_length = map_length;
+    dp.colormap_length = maps.colors;
+    if rows.end == dp.ypix {
+        dp.block = grid.step;
+        if grid.step == 1 {
+            if let Some(mirror) = dp.mirror.take() {
+                mirror.fill(dp.xpix, dp.texture_axis, px.itermap, px.interior,
+                            px.orbit, px.state);
+            }
+            dp.kept = None;
+        }
+    }
 }
 
 /**
@@ -510,47 +1811,87 @@ map has increased since the last time `iterate()` was called, this function
 will use the last-used iterator to reiterate _only_ the points who would
 have iterated off the end of the old color map. This makes recoloring still
 pretty fast while working as one would expect.
+
+Those are also the only points whose `INTERIOR` values mean anything, so
+this is also how a change of interior metric (or a shortened color map,
+which changes how far the interior orbits are followed) gets applied. In
+that case the threshold is the shorter of the two color maps.
+
+When the color map hasn't got shorter, and `STATE` holds the orbit of every
+point that didn't diverge (which it does when `dp.interior_exact`, once
+every pixel has been iterated, so `dp.block` is 1), those
+orbits carry on from where they stopped instead of starting over, so going
+from 100 shades to 200 costs only the 100 new iterations, and a change of
+interior metric costs no iterations at all. Either way, it's all done in
+the precision the image was iterated in (and in double-double precision,
+the orbits always start over; see the `dd` module, and likewise with
+perturbation, which needs a new reference orbit in `refs` for it).
 */
 fn reiterate(
     dp: &DrawParams,
-    buff: &mut [u16; IMAGE_SIZE],
+    mut px: Pixels,
     map_length: usize,
-    coeffs: &[Cx; MAX_COEFFS],
-    n_coeffs: usize,
+    metric: InteriorMetric,
+    refs: &mut perturb::References,
 ) {
-    // Limit number of polynomial terms to sane amount.
-    let degree = if n_coeffs < 1 { return; }    // Stop; this is stupid.
-            else if n_coeffs > MAX_COEFFS { MAX_COEFFS-1 }
-            else { n_coeffs-1 };
-    
-    let xpixf = dp.xpix as f64;
-    let ypixf = dp.ypix as f64;
-    let height = dp.width * ypixf / xpixf;
-    
+    let poly = match Poly::new(&dp.coeffs, dp.n_coeffs) {
+        Some(p) => p,
+        None => { return; },    // Stop; this is stupid.
+    };
+    let limits = Limits { metric, ..dp.limits(map_length) };
+
+
     let n_shades = map_length as u16;
     let old_n_shades = dp.colormap_length as u16;
+    let threshold = if n_shades < old_n_shades { n_shades } else { old_n_shades };
     let iterator = dp.iterator;
+    let precision = dp.precision();
+    let resume = dp.interior_exact && dp.block == 1 && n_shades >= old_n_shades
+        && (precision == Precision::Single || precision == Precision::Double);
+    if precision == Precision::Perturbation {
+        let center = dp.offset_at(dp.xpix / 2, dp.ypix / 2);
+        refs.main.compute(dp, center, &limits);
+    }
+    let reference = &refs.main;
     
-    for yp in 0..dp.ypix {
-        let y_val = dp.y - height * ((yp as f64) / ypixf);
-        let idx_base: usize = yp * dp.xpix;
-        for xp in 0..dp.xpix {
-            let idx = idx_base + xp;
-            if buff[idx] >= old_n_shades {
-                let x_val = dp.x + dp.width * ((xp as f64) / xpixf);
-                let n = match iterator {
-                    IteratorType::Mandelbrot => mandelbrot_iter(
-                        x_val, y_val, SQ_MOD_LIMIT, n_shades
-                    ),
-                    IteratorType::Polynomial => polynomial_iter(
-                        x_val, y_val,
-                        coeffs, degree,
-                        SQ_MOD_LIMIT, n_shades
-                    ),
-                };
-                buff[idx] = n;
+    bands::in_bands(dp.xpix, dp.ypix, px.starting_at(0), |rows, mut px| {
+        for yp in rows.clone() {
+            let idx_base: usize = (yp - rows.start) * dp.xpix;
+            for xp in 0..dp.xpix {
+                let idx = idx_base + xp;
+                if px.itermap[idx] >= threshold {
+                    let c = dp.point_at(xp, yp);
+                    let (n0, start) = match (resume, iterator) {
+                        (true, _) => (old_n_shades, px.state[idx]),
+                        (false, IteratorType::Mandelbrot) =>
+                            (0, OrbitState::start(Cx { re: 0.0, im: 0.0 })),
+                        (false, IteratorType::Polynomial) =>
+                            (0, OrbitState::start(c)),
+                    };
+                    let res = match (precision, iterator) {
+                        (Precision::Single, IteratorType::Mandelbrot) =>
+                            single::mandelbrot_iter(&c, n0, &start, &limits),
+                        (Precision::Single, IteratorType::Polynomial) =>
+                            single::polynomial_iter(n0, &start, &poly, &limits),
+                        (Precision::Double, IteratorType::Mandelbrot) =>
+                            mandelbrot_iter_from(c.re, c.im, n0, &start, &limits),
+                        (Precision::Double, IteratorType::Polynomial) =>
+                            polynomial_iter_from(n0, &start, &poly, &limits),
+                        (Precision::DoubleDouble, IteratorType::Mandelbrot) =>
+                            dd::mandelbrot_iter(&dp.point_dd(xp, yp), &limits),
+                        (Precision::DoubleDouble, IteratorType::Polynomial) =>
+                            dd::polynomial_iter(&dp.point_dd(xp, yp), &poly, &limits),
+                        (Precision::Perturbation, _) => perturb::mandelbrot_iter(
+                            reference, dp.offset_at(xp, yp).sub(&reference.offset), &limits
+                        ),
+                    };
+                    px.set(idx, &res);
+                }
             }
         }
+    });
+    if precision == Precision::Perturbation {
+        perturb::fix_glitches(dp, &mut refs.spare, Grid::ALL, px, &limits);
     }
 }
 
@@ -595,7 +1936,18 @@ impl PixVal {
 const ORTH: f32 = 0.184699031259;
 const DIAG: f32 = 0.130601937482;
 
-fn smooth_image(dp: &DrawParams, amt: f32, buff: &mut [u32; IMAGE_SIZE]) {
+/**
+Blend each pixel of `buff` with the ones to its right, below it, and
+diagonally below right, by `amt`. It's done in place, top to bottom, so
+each row is blended with the row below before that row gets blended
+itself.
+
+Unlike the coloring, this isn't split into bands (see the `bands` module):
+the last row of each band would need the next band's first row as it was
+before that band got to it. It's one cheap pass over the image, next to
+iterating it.
+*/
+fn smooth_image(dp: &DrawParams, amt: f32, buff: &mut [u32]) {
     //dbg_msg("smoothing: "); dbg_float(amt as f64); dbg_msg("\n");
     let orth = ORTH * amt;
     let diag = DIAG * amt;
@@ -630,70 +1982,1104 @@ fn smooth_image(dp: &DrawParams, amt: f32, buff: &mut [u32; IMAGE_SIZE]) {
 }
 
 /**
-Exported function to rewrite the iteration map after changing the view
-on the plane or the size of the image. Also calls `color_itermap()` to
-rewrite the `IMAGE` data.
-  * `xpix` and `ypix`: image dimensions in pixels.
-  * `x` and `y`: coordinates of the upper-left-hand corner of the image
-  * `width`: the width of the image on the Complex Plaine
-  * `use_polynomial_iterator`: if this is `false`, the Mandlebrot iterator
-    will be used to create the iteration map; if `true`, the polynomial
-    iterator will be used
+Exported function to set the most memory (in bytes) the per-pixel buffers
+are allowed to take up. They need 36 bytes per pixel. Lowering the cap
+doesn't give back memory that's already been taken (wasm can't do that),
+but it does stop any more from being taken.
+
+# Safety
+
+Writes `BUFFERS`; see the crate docs.
 */
 #[no_mangle]
-pub unsafe extern fn redraw(
-    x_pixels: usize, y_pixels: usize,
-    re: f64, im: f64,
-    img_width: f64,
-    use_polynomial_iterator: bool,
+pub unsafe extern fn set_memory_cap(bytes: usize) {
+    buffers().set_cap(bytes);
+}
+
+/**
+Exported function to get the location of the `IMAGE` buffer in module
+memory, or a null pointer before there's been any image. Once there has,
+it stays put (the buffers grow off the end of memory; see the `buffers`
+module), but growing memory replaces the module's `memory.buffer`, so any
+view of the image made before a `redraw()` (or `reserve_image()`) has to
+be made again over the new one.
+
+# Safety
+
+Reads `BUFFERS`; see the crate docs. What the pointer points to is only
+the current image until the next call that draws anything.
+*/
+#[no_mangle]
+pub unsafe extern fn image_ptr() -> *mut u32 {
+    buffers().image_ptr()
+}
+
+/**
+Exported function to make sure the `IMAGE` buffer has room for `n_pixels`
+pixels (for writing a reference image into it for `extract_palette()`, say).
+Returns its location (the same as `image_ptr()`'s), or a null pointer if
+that many pixels won't fit under the memory cap. The current image
+survives either way.
+
+# Safety
+
+Can grow `BUFFERS` (and memory); see the crate docs. Write no more than
+`n_pixels` pixels through the pointer.
+*/
+#[no_mangle]
+pub unsafe extern fn reserve_image(n_pixels: usize) -> *mut u32 {
+    let buffers = buffers();
+    if buffers.reserve(n_pixels) { buffers.image_ptr() }
+    else { core::ptr::null_mut() }
+}
+
+/**
+Exported function to rewrite the iteration map after changing the view
+on the plane or the size of the image. Also calls `color_itermap()` to
+rewrite the `IMAGE` data.
+  * `xpix` and `ypix`: image dimensions in pixels.
+  * `x` and `y`: coordinates of the upper-left-hand corner of the image
+  * `width`: the width of the image on the Complex Plaine
+  * `use_polynomial_iterator`: if this is `false`, the Mandlebrot iterator
+    will be used to create the iteration map; if `true`, the polynomial
+    iterator will be used
+
+The buffers grow to fit the image if they have to. Returns `false` (and
+draws nothing) if the image would take more memory than the cap allows.
+
+# Safety
+
+Reads the settings the other exported functions write, and writes
+`DRAW_PARAMS` and the per-pixel buffers; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn redraw(
+    x_pixels: usize, y_pixels: usize,
+    re: f64, im: f64,
+    img_width: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    render_tile(
+        x_pixels, y_pixels,
+        0, 0, x_pixels, y_pixels,
+        re, im, img_width,
+        use_polynomial_iterator, smooth
+    )
+}
+
+/**
+Exported function that works just like `redraw()`, but with the coordinates
+of the corner in double-double precision: the real one is `re_hi + re_lo`
+and the imaginary one `im_hi + im_lo` (see the `dd` module). Once the image
+is less than about `1e-10` wide on the plane, a pixel is only a few hundred
+ulps of an `f64` across, and `f64` coordinates can't put the corner where
+it belongs; the `lo` parts carry the rest of the digits. `redraw()` is this
+with both of them 0.
+
+# Safety
+
+The same as `redraw()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn redraw_dd(
+    x_pixels: usize, y_pixels: usize,
+    re_hi: f64, re_lo: f64,
+    im_hi: f64, im_lo: f64,
+    img_width: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    let view = View::current(Dd::new(re_hi, re_lo), Dd::new(im_hi, im_lo), NO_REST, img_width);
+    render(&Tile::whole(x_pixels, y_pixels), &view, use_polynomial_iterator, smooth)
+}
+
+/**
+Exported function to set the matrix that rotates, skews, or otherwise
+linearly transforms the view on the next `redraw()` (or `render_tile()`).
+It takes each pixel's offset from the upper-left-hand corner of the image,
+as it would be without the matrix, to its offset on the plane:
+
+```text
+    re offset = a * (offset right) + b * (offset up)
+    im offset = c * (offset right) + d * (offset up)
+```
+
+so the corner stays put and the rest of the image pivots around it. The
+identity matrix (1, 0, 0, 1) is the default, ordinary view.
+
+# Safety
+
+Writes `VIEW_MATRIX`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_view_transform(a: f64, b: f64, c: f64, d: f64) {
+    VIEW_MATRIX = [a, b, c, d];
+}
+
+/**
+Exported function to set the view matrix (see `set_view_transform()`) to a
+plain rotation by `angle` radians. Positive angles turn the view
+counterclockwise, so what's in the image appears to turn clockwise.
+
+# Safety
+
+Writes `VIEW_MATRIX`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_view_angle(angle: f64) {
+    let (cos, sin) = (fmath::cos(angle), fmath::sin(angle));
+    VIEW_MATRIX = [cos, -sin, sin, cos];
+}
+
+/**
+Exported function to pick how the next `redraw()` (or `render_tile()`)
+goes about iterating the image:
+  * 0: brute force, every pixel one by one (the default)
+  * 1: Mariani–Silver subdivision, which skips the insides of rectangles
+    whose edges all took the same number of iterations; much faster for
+    images with big flat areas, but it can miss the odd speck (see the
+    `subdivide` module)
+  * 2: boundary tracing, which iterates only the outlines of regions that
+    took the same number of iterations and fills in their insides; it
+    only misses features thinner than a pixel (see the `boundary` module)
+
+It only applies when a whole image gets iterated at once; progressive
+renders, and time-sliced ones done in more than one step, use brute force.
+
+# Safety
+
+Writes `STRATEGY`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_strategy(strategy: u8) {
+    STRATEGY = match strategy {
+        1 => Strategy::Subdivide,
+        2 => Strategy::BoundaryTrace,
+        _ => Strategy::BruteForce,
+    };
+}
+
+/**
+Exported function to choose the precision the iterators do their arithmetic
+in, mainly for testing:
+  * 0: whatever suits the view (the default): single precision (`f32`) for
+    shallow zooms of the Mandelbrot set, where it's about twice as fast,
+    double (`f64`) once pixels get too small for it (and from the start for
+    the polynomial iterator), and perturbation (see the `perturb` module),
+    or double-double (see the `dd` module) for the polynomial iterator,
+    once they get too small for that (see `DrawParams::precision()`)
+  * 1: always single precision
+  * 2: always double precision
+  * 3: always double-double precision
+  * 4: always perturbation (for the Mandelbrot iterator; double-double for
+    the polynomial one)
+
+Wherever single precision gets picked automatically, the iteration counts
+it comes up with (with the built-in palettes' few hundred shades) match
+double precision's for at least 99% of the pixels. The pixels that don't
+match are where the count depends on rounding in any precision: along the
+edge of the set, next to pixels whose counts differ too. Fewer than 15
+pixels in a million don't match even though their neighbors all agree with
+each other in double precision. Smoothed shades and texture coordinates
+come out a hair different everywhere, but not visibly. Forced on the
+polynomial iterator, it gets a good deal more counts wrong near the edge of
+a chaotic Julia set, which is why it never gets picked there.
+
+# Safety
+
+Writes `FORCE_PRECISION`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_precision(precision: u8) {
+    FORCE_PRECISION = match precision {
+        1 => Some(Precision::Single),
+        2 => Some(Precision::Double),
+        3 => Some(Precision::DoubleDouble),
+        4 => Some(Precision::Perturbation),
+        _ => None,
+    };
+}
+
+/**
+Exported function to turn off any warp set by the functions below, so the
+next `redraw()` shows the plane undistorted.
+
+# Safety
+
+Writes `WARP`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_warp_none() {
+    WARP = Warp::Identity;
+}
+
+/**
+Exported function to make the next `redraw()` show the plane inverted
+(`z` goes to `p + 1/(z - p)`) about the point `p` = `re` + `im`i. Points
+near `p` get flung out toward infinity, and the far reaches of the plane
+(the outside of the Mandelbrot set, say) gather around `p`.
+
+# Safety
+
+Writes `WARP`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_warp_inversion(re: f64, im: f64) {
+    WARP = Warp::Inversion(Cx { re, im });
+}
+
+/**
+Exported function to make the next `redraw()` show the plane through the
+Möbius transformation `z` goes to `(az + b)/(cz + d)`. Each coefficient is
+given as its real part followed by its imaginary part. (If `ad - bc` is
+zero, the whole image is one point, which is not very interesting.)
+
+# Safety
+
+Writes `WARP`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_warp_mobius(
+    a_re: f64, a_im: f64,
+    b_re: f64, b_im: f64,
+    c_re: f64, c_im: f64,
+    d_re: f64, d_im: f64,
+) {
+    WARP = Warp::Mobius([
+        Cx { re: a_re, im: a_im },
+        Cx { re: b_re, im: b_im },
+        Cx { re: c_re, im: c_im },
+        Cx { re: d_re, im: d_im },
+    ]);
+}
+
+/**
+Exported function to make the next `redraw()` show the plane through a lens
+centered on `re` + `im`i. At distance `radius` from the center, points are
+pushed out (for positive `strength`, a fisheye or barrel distortion) or
+pulled in (for negative `strength`, pincushion) by a factor of
+`1 + strength`; the effect grows with the square of the distance.
+
+# Safety
+
+Writes `WARP`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_warp_fisheye(re: f64, im: f64, radius: f64, strength: f64) {
+    WARP = Warp::Fisheye { center: Cx { re, im }, radius, strength };
+}
+
+/**
+Exported function to make the next `redraw()` unroll the plane around the
+point `re` + `im`i: the real axis of the view becomes the angle around that
+point (in radians) and the imaginary axis the distance from it. So, say, a
+`redraw()` with corner (0, 2) and width 2π shows a ring of radius up to 2
+around the point, cut open and laid flat.
+
+# Safety
+
+Writes `WARP`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn set_warp_polar(re: f64, im: f64) {
+    WARP = Warp::Polar(Cx { re, im });
+}
+
+/// Width on the complex plane of a view centered with a zoom of 1.
+const CENTERED_WIDTH: f64 = 4.0;
+
+/**
+Exported function that works just like `redraw()`, except that the view is
+given by the point `re` + `im`i at the center of the image and a `zoom`
+factor (the image is `CENTERED_WIDTH / zoom` wide on the plane) instead of
+by its corner. The current view matrix is applied about the center, too, so
+this is the easy way to draw a rotated view of something.
+
+# Safety
+
+The same as `redraw()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn redraw_centered(
+    x_pixels: usize, y_pixels: usize,
+    re: f64, im: f64,
+    zoom: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    let width = CENTERED_WIDTH / zoom;
+    let half_w = width / 2.0;
+    let half_h = (width * (y_pixels as f64) / (x_pixels as f64)) / 2.0;
+    let [a, b, c, d] = VIEW_MATRIX;
+    // The center is offset half the width right and half the height down.
+    redraw(
+        x_pixels, y_pixels,
+        re - (a * half_w - b * half_h),
+        im - (c * half_w - d * half_h),
+        width,
+        use_polynomial_iterator, smooth
+    )
+}
+
+/// Room in `COORDS` for the coordinates given to `redraw_exact()`.
+const COORDS_LENGTH: usize = 4096;
+
+/// Where the Javascript writes the coordinates for `redraw_exact()`.
+static mut COORDS: [u8; COORDS_LENGTH] = [0; COORDS_LENGTH];
+
+/**
+Exported function to get the location in module memory of the buffer the
+coordinates for `redraw_exact()` go in. It has room for `COORDS_LENGTH`
+(4096) bytes, and it doesn't move.
+
+# Safety
+
+Write no more than `COORDS_LENGTH` bytes through the pointer, and only
+between calls of the other exported functions (see the crate docs).
+*/
+#[no_mangle]
+pub unsafe extern fn coords_ptr() -> *mut u8 {
+    core::ptr::addr_of_mut!(COORDS) as *mut u8
+}
+
+/**
+The view `redraw_exact()` describes, with the current view matrix and warp.
+*/
+unsafe fn exact_view(
+    x_pixels: usize, y_pixels: usize,
+    re_len: usize, im_len: usize, zoom_len: usize,
+) -> Option<View> {
+    let im_start = re_len;
+    let zoom_start = im_start.checked_add(im_len)?;
+    let end = zoom_start.checked_add(zoom_len)?;
+    if end > COORDS_LENGTH || x_pixels == 0 { return None; }
+    let coords = &*core::ptr::addr_of!(COORDS);
+    let re = Fixed::parse(&coords[..im_start], fixed::MAX_LIMBS)?;
+    let im = Fixed::parse(&coords[im_start..zoom_start], fixed::MAX_LIMBS)?;
+    let zoom: f64 = core::str::from_utf8(&coords[zoom_start..end]).ok()?.parse().ok()?;
+    if zoom.is_nan() || zoom <= 0.0 || !zoom.is_finite() { return None; }
+
+    // Just as in redraw_centered(), but exactly.
+    let width = CENTERED_WIDTH / zoom;
+    let half_w = width / 2.0;
+    let half_h = (width * (y_pixels as f64) / (x_pixels as f64)) / 2.0;
+    let [a, b, c, d] = VIEW_MATRIX;
+    let offset = |x: f64| Fixed::from_f64(x, fixed::MAX_LIMBS);
+    let (re, re_rest) = re.sub(&offset(a * half_w - b * half_h)).split_dd();
+    let (im, im_rest) = im.sub(&offset(c * half_w - d * half_h)).split_dd();
+    Some(View::current(re, im, [re_rest, im_rest], width))
+}
+
+/**
+Exported function that works just like `redraw_centered()`, but with the
+center and zoom given as decimal numbers (like `-0.75`, `1.5e-40`, or `3`),
+in as many digits as it takes. They go in `COORDS` (see `coords_ptr()`) as
+ASCII, one after the other: the real part of the center in the first
+`re_len` bytes, then the imaginary part in the next `im_len`, then the
+zoom in the next `zoom_len`. That's for bookmarks and links to places too
+deep for `f64`s (or double-doubles) to say where they are; the center is
+kept to about `1e-330`, and the zoom (an `f64` anyway) can go to about
+`1e300`. Only perturbation can use all those digits, though, so this is
+only good past double-double precision for the Mandelbrot iterator.
+
+Returns `false` (and draws nothing) if the coordinates don't parse (or the
+whole part of either one doesn't fit in 32 bits) or the image won't fit
+under the memory cap.
+
+# Safety
+
+The same as `redraw()`'s, and it reads `COORDS`.
+*/
+#[no_mangle]
+pub unsafe extern fn redraw_exact(
+    x_pixels: usize, y_pixels: usize,
+    re_len: usize, im_len: usize, zoom_len: usize,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    let view = match exact_view(x_pixels, y_pixels, re_len, im_len, zoom_len) {
+        Some(v) => v,
+        None => { return false; },
+    };
+    render(&Tile::whole(x_pixels, y_pixels), &view, use_polynomial_iterator, smooth)
+}
+
+/**
+Exported function to draw just one rectangular tile of an image that's
+(potentially) far too big to draw all at once. The image is described just
+as for `redraw()`, by its full dimensions `full_x_pixels` and
+`full_y_pixels`, the coordinates `re` and `im` of its upper-left-hand
+corner, and its width `img_width` on the plane. The tile is `tile_width` by
+`tile_height` pixels, with its upper-left-hand corner at column `tile_x` and
+row `tile_y` of the whole image; only it gets written to `IMAGE`.
+
+Every pixel comes out exactly the same as it would in the whole image,
+smoothing included, so the tiles can just be pasted together. (Smoothing
+looks at each pixel's neighbors to the right and below, so tiles not on the
+right or bottom edge get an extra column or row iterated for that purpose.)
+
+Returns `false` (and draws nothing) if the tile doesn't fit in the image or
+won't fit under the memory cap.
+
+# Safety
+
+The same as `redraw()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn render_tile(
+    full_x_pixels: usize, full_y_pixels: usize,
+    tile_x: usize, tile_y: usize,
+    tile_width: usize, tile_height: usize,
+    re: f64, im: f64,
+    img_width: f64,
+    use_polynomial_iterator: bool,
     smooth: f32,
+) -> bool {
+    let tile = Tile {
+        xpix: tile_width, ypix: tile_height,
+        full_xpix: full_x_pixels, full_ypix: full_y_pixels,
+        x_off: tile_x, y_off: tile_y,
+    };
+    let view = View::current(Dd::from(re), Dd::from(im), NO_REST, img_width);
+    render(&tile, &view, use_polynomial_iterator, smooth)
+}
+
+/**
+Where an image is on the plane: the coordinates of its upper-left-hand
+corner, in double-double precision (`re` and `im`), with whatever's left of
+them past that in `rest` (see `DrawParams`), its `width` on the plane, and
+the view `matrix` and `warp`.
+*/
+#[derive(Clone, Copy)]
+struct View {
+    re: Dd,
+    im: Dd,
+    rest: [Fixed; 2],
+    width: f64,
+    matrix: [f64; 4],
+    warp: Warp,
+}
+
+impl View {
+    /// The view with the given corner and width, and `VIEW_MATRIX` and `WARP`.
+    unsafe fn current(re: Dd, im: Dd, rest: [Fixed; 2], width: f64) -> View {
+        View { re, im, rest, width, matrix: VIEW_MATRIX, warp: WARP }
+    }
+}
+
+/**
+Which part of an image gets drawn: `xpix` by `ypix` pixels, starting at
+column `x_off` and row `y_off` of the whole image, which is `full_xpix` by
+`full_ypix` pixels (see `render_tile()`).
+*/
+#[derive(Clone, Copy)]
+struct Tile {
+    xpix: usize,
+    ypix: usize,
+    full_xpix: usize,
+    full_ypix: usize,
+    x_off: usize,
+    y_off: usize,
+}
+
+impl Tile {
+    /// All of an `xpix` by `ypix` image.
+    fn whole(xpix: usize, ypix: usize) -> Tile {
+        Tile { xpix, ypix, full_xpix: xpix, full_ypix: ypix, x_off: 0, y_off: 0 }
+    }
+}
+
+/**
+Does the work of `render_tile()` (which see), with the `tile` and the
+`view` (whose corner can be given in more than double precision) spelled
+out.
+*/
+unsafe fn render(
+    tile: &Tile,
+    view: &View,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    let fits = |off: usize, len: usize, full: usize| match off.checked_add(len) {
+        Some(end) => end <= full,
+        None => false,
+    };
+    if !fits(tile.x_off, tile.xpix, tile.full_xpix)
+        || !fits(tile.y_off, tile.ypix, tile.full_ypix)
+    { return false; }
+    
+    let smooth_frac = smooth_fraction_of(smooth);
+    let apron = |off: usize, len: usize, full: usize| {
+        if smooth_frac.is_some() && off + len < full { 1 } else { 0 }
+    };
+    let apron_x = apron(tile.x_off, tile.xpix, tile.full_xpix);
+    let apron_y = apron(tile.y_off, tile.ypix, tile.full_ypix);
+    let (xpix, ypix) = (tile.xpix + apron_x, tile.ypix + apron_y);
+    
+    let npix = match xpix.checked_mul(ypix) {
+        Some(n) => n,
+        None => { return false; },
+    };
+    if !buffers().reserve(npix) { return false; }
+    
+    let old = DRAW_PARAMS;
+    set_draw_params(tile, (apron_x, apron_y), view, use_polynomial_iterator, smooth_frac);
+    reuse_previous(&old);
+    mirror_symmetric();
+    
+    let all_rows = 0..DRAW_PARAMS.ypix;
+    iterate(draw_params(), Grid::ALL, all_rows, buffers().pixels(), buffers().image(),
+            MapLengths::current(), references());
+    
+    color_image();
+    true
+}
+
+/**
+Set `DRAW_PARAMS` up for a new image (or `tile`) of `view` that hasn't been
+iterated yet, with `apron` extra columns and rows. The buffers should
+already be big enough.
+*/
+unsafe fn set_draw_params(
+    tile: &Tile,
+    (apron_x, apron_y): (usize, usize),
+    view: &View,
+    use_polynomial_iterator: bool,
+    smooth_frac: Option<f32>,
 ) {
+    let (re, im) = (view.re, view.im);
     DRAW_PARAMS = DrawParams {
-        xpix: if x_pixels > MAX_WIDTH  { MAX_WIDTH  } else { x_pixels },
-        ypix: if y_pixels > MAX_HEIGHT { MAX_HEIGHT } else { y_pixels },
-        x: re, y: im, width: img_width,
+        xpix: tile.xpix + apron_x, ypix: tile.ypix + apron_y,
+        x: re.hi, y: im.hi, x_lo: re.lo, y_lo: im.lo, width: view.width,
+        x_rest: view.rest[0], y_rest: view.rest[1],
+        full_xpix: tile.full_xpix, full_ypix: tile.full_ypix,
+        x_off: tile.x_off, y_off: tile.y_off,
+        apron_x, apron_y,
+        matrix: view.matrix, warp: view.warp,
+        block: 0,
+        rendering: false, progressive: false, cursor: 0,
+        maps: MapLengths::current(),
         colormap_length: DRAW_PARAMS.colormap_length,
         iterator: match use_polynomial_iterator {
             true  => IteratorType::Polynomial,
             false => IteratorType::Mandelbrot,
         },
-        smooth_frac: if smooth < 0.05 { None }
-                     else if smooth > 0.99 { Some(1.0) }
-                     else { Some(smooth) },
+        coeffs: COEFFS, n_coeffs: N_COEFFS,
+        smooth_frac,
+        strategy: STRATEGY,
+        interior_exact: true,
+        interior_metric: INTERIOR_METRIC,
+        texture_axis: TEXTURE_AXIS,
+        kept: None,
+        mirror: None,
+        forced_precision: FORCE_PRECISION,
+    };
+}
+
+/**
+Carry over whatever of the data from the last image (described by `old`)
+the one `DRAW_PARAMS` now describes can use (see the `reuse` module), so
+only the rest needs iterating.
+*/
+unsafe fn reuse_previous(old: &DrawParams) {
+    // Made-up interior data are no good once there's an interior color map.
+    if !old.interior_exact && CURRENT_INTERIOR_MAP_LENGTH != 0 { return; }
+    let dp = draw_params();
+    let buffers = buffers();
+    dp.kept = reuse::reuse(
+        old, dp, CURRENT_COLORMAP_LENGTH,
+        buffers.itermap(), buffers.interior(), buffers.orbit(), buffers.state()
+    );
+    if dp.kept.is_some() { dp.interior_exact = old.interior_exact; }
+}
+
+/**
+If the image `DRAW_PARAMS` describes is symmetric (see the `symmetry`
+module), set it up to be iterated only halfway, unless more than that was
+carried over from the last image.
+*/
+unsafe fn mirror_symmetric() {
+    let dp = draw_params();
+    let mirror = match symmetry::find(dp, &dp.coeffs, dp.n_coeffs) {
+        Some(m) => m,
+        None => { return; },
+    };
+    if let Some(kept) = &dp.kept {
+        if kept.count() >= mirror.kept().count() { return; }
+    }
+    dp.mirror = Some(mirror);
+    dp.kept = Some(mirror.kept());
+    dp.interior_exact = true;
+}
+
+/// How much smoothing a `smooth` argument from Javascript actually means.
+fn smooth_fraction_of(smooth: f32) -> Option<f32> {
+    if smooth < 0.05 { None }
+    else if smooth > 0.99 { Some(1.0) }
+    else { Some(smooth) }
+}
+
+/**
+Exported function to start drawing an image a bit at a time, so the
+Javascript event loop can keep going in between (and abandon the image if
+it's not wanted anymore). It takes the same arguments as `redraw()` (and
+returns `false` for the same reason), plus whether to draw `progressive`ly
+(coarse to fine; see `progressive_step()`), but doesn't draw anything
+itself; call `render_step()` until `render_done()` to do that.
+
+# Safety
+
+Writes `DRAW_PARAMS` (and can grow the per-pixel buffers); see the crate
+docs.
+*/
+#[no_mangle]
+pub unsafe extern fn render_begin(
+    x_pixels: usize, y_pixels: usize,
+    re: f64, im: f64,
+    img_width: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+    progressive: bool,
+) -> bool {
+    render_begin_dd(
+        x_pixels, y_pixels, re, 0.0, im, 0.0, img_width,
+        use_polynomial_iterator, smooth, progressive
+    )
+}
+
+/**
+Exported function that works just like `render_begin()`, but with the
+corner given in double-double precision, as for `redraw_dd()`.
+
+# Safety
+
+The same as `render_begin()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn render_begin_dd(
+    x_pixels: usize, y_pixels: usize,
+    re_hi: f64, re_lo: f64,
+    im_hi: f64, im_lo: f64,
+    img_width: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+    progressive: bool,
+) -> bool {
+    let view = View::current(Dd::new(re_hi, re_lo), Dd::new(im_hi, im_lo), NO_REST, img_width);
+    begin(x_pixels, y_pixels, &view, use_polynomial_iterator, smooth, progressive)
+}
+
+/**
+Does the work of `render_begin_dd()` (and `render_begin_exact()`), with the
+`view` spelled out.
+*/
+unsafe fn begin(
+    x_pixels: usize, y_pixels: usize,
+    view: &View,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+    progressive: bool,
+) -> bool {
+    let npix = match x_pixels.checked_mul(y_pixels) {
+        Some(n) => n,
+        None => { return false; },
+    };
+    if !buffers().reserve(npix) { return false; }
+    let old = DRAW_PARAMS;
+    set_draw_params(
+        &Tile::whole(x_pixels, y_pixels), (0, 0), view,
+        use_polynomial_iterator, smooth_fraction_of(smooth)
+    );
+    reuse_previous(&old);
+    mirror_symmetric();
+    let dp = draw_params();
+    // There's too little left to do to bother with coarse passes.
+    dp.progressive = progressive && (dp.kept.is_none() || dp.mirror.is_some());
+    dp.rendering = true;
+    true
+}
+
+/**
+Exported function that works just like `render_begin()`, but with the
+view given as for `redraw_exact()`.
+
+# Safety
+
+The same as `render_begin()`'s, and it reads `COORDS`.
+*/
+#[no_mangle]
+pub unsafe extern fn render_begin_exact(
+    x_pixels: usize, y_pixels: usize,
+    re_len: usize, im_len: usize, zoom_len: usize,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+    progressive: bool,
+) -> bool {
+    let view = match exact_view(x_pixels, y_pixels, re_len, im_len, zoom_len) {
+        Some(v) => v,
+        None => { return false; },
+    };
+    begin(x_pixels, y_pixels, &view, use_polynomial_iterator, smooth, progressive)
+}
+
+/**
+Exported function to do (about) `max_pixels` more pixels' worth of the
+render started by `render_begin()`. The work is done a row at a time, so
+it's rounded to whole rows, but it always does at least one. It stops early
+at the end of a pass, though (there's only one pass unless the render is
+progressive), and colors the image into `IMAGE`.
+
+The step that finishes a render iterated with perturbation (see the
+`perturb` module) also fixes all of its glitched pixels, however many
+there are, so that one can take well over its budget.
+
+Returns `true` if `IMAGE` got updated (that is, a pass finished).
+
+# Safety
+
+Reads and writes `DRAW_PARAMS` and the per-pixel buffers; see the crate
+docs.
+*/
+#[no_mangle]
+pub unsafe extern fn render_step(max_pixels: usize) -> bool {
+    if !DRAW_PARAMS.rendering { return false; }
+    let dp = draw_params();
+    let grid = match (dp.block, dp.progressive) {
+        (0, true)  => Grid { step: COARSEST_STEP, first: true },
+        (0, false) => Grid::ALL,
+        (b, _)     => Grid { step: b / 2, first: false },
     };
     
-    iterate(&mut DRAW_PARAMS, &mut ITERMAP, CURRENT_COLORMAP_LENGTH,
-            &COEFFS, N_COEFFS);
+    // Take rows until they've used up the budget.
+    let start = dp.cursor;
+    let mut end = start;
+    let mut pixels = 0;
+    while end < dp.ypix && (pixels == 0 || pixels < max_pixels) {
+        if let Some((x0, dx)) = grid.columns(end) {
+            pixels += match (&dp.kept, grid.step) {
+                (Some(kept), 1) => kept.todo_in_row(dp.xpix, end),
+                _ => (dp.xpix + dx - 1 - x0) / dx,
+            };
+        }
+        end += 1;
+    }
     
-    color_itermap(
-        &ITERMAP, &COLOR_MAP, &mut IMAGE, DEFAULT_COLOR,
-        DRAW_PARAMS.xpix * DRAW_PARAMS.ypix, CURRENT_COLORMAP_LENGTH
-    );
+    iterate(dp, grid, start..end, buffers().pixels(), buffers().image(),
+            dp.maps, references());
+    if end < dp.ypix {
+        dp.cursor = end;
+        return false;
+    }
     
-    if let Some(f) = DRAW_PARAMS.smooth_frac {
-        smooth_image(&DRAW_PARAMS, f, &mut IMAGE);
+    fill_blocks(dp, buffers().pixels());
+    dp.cursor = 0;
+    if dp.block == 1 { dp.rendering = false; }
+    color_image();
+    true
+}
+
+/// Exported function to find out whether the current render is finished.
+///
+/// # Safety
+///
+/// Reads `DRAW_PARAMS`; see the crate docs.
+#[no_mangle]
+pub unsafe extern fn render_done() -> bool {
+    !DRAW_PARAMS.rendering
+}
+
+/**
+Exported function to abandon the current render. `IMAGE` is left with
+whatever the last finished pass of it drew, if anything.
+
+# Safety
+
+Writes `DRAW_PARAMS`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn render_cancel() {
+    DRAW_PARAMS.rendering = false;
+}
+
+/**
+Exported function to start drawing an image progressively, coarse to fine,
+so there's something to look at right away. It's `render_begin()` with
+`progressive` set; call `progressive_step()` (or `render_step()`) until the
+image is done.
+
+# Safety
+
+The same as `render_begin()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn progressive_begin(
+    x_pixels: usize, y_pixels: usize,
+    re: f64, im: f64,
+    img_width: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    render_begin(
+        x_pixels, y_pixels, re, im, img_width,
+        use_polynomial_iterator, smooth, true
+    )
+}
+
+/**
+Exported function to do the next pass of a progressive render (see
+`progressive_begin()`) all at once and write the result to `IMAGE`. The
+first pass iterates every 8th pixel of every 8th row and draws each one as
+an 8 x 8 block; each pass after that halves the blocks, iterating only the
+pixels that the passes before didn't, until the last pass fills in the rest.
+
+Returns the size of the blocks the image is now made of (8, 4, 2, and
+finally 1), or 0 if there's nothing left to do.
+
+# Safety
+
+The same as `render_step()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn progressive_step() -> usize {
+    if !DRAW_PARAMS.rendering { return 0; }
+    while !render_step(usize::MAX) {}
+    DRAW_PARAMS.block
+}
+
+/**
+Exported function to find out how far along the current image is: the size
+of the blocks it's made of, so 1 when it's finished, bigger partway through
+a progressive render, and 0 if nothing's been iterated yet.
+
+# Safety
+
+Reads `DRAW_PARAMS`; see the crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn progressive_block() -> usize {
+    DRAW_PARAMS.block
+}
+
+/**
+Copy each iterated pixel's data over the rest of its `dp.block`-sized block
+(the pixels to the right and below it that haven't been iterated yet).
+Not its orbit in `STATE`, though: that's its own point's, so it's no good
+for carrying on with any other (see `reiterate()`).
+*/
+fn fill_blocks(dp: &DrawParams, px: Pixels) {
+    let Pixels { itermap: buff, interior, orbit, .. } = px;
+    let b = dp.block;
+    if b < 2 { return; }
+    for yp in (0..dp.ypix).step_by(b) {
+        for xp in (0..dp.xpix).step_by(b) {
+            let src = yp * dp.xpix + xp;
+            let (n, int, orb) = (buff[src], interior[src], orbit[src]);
+            for y in yp..core::cmp::min(yp + b, dp.ypix) {
+                for x in xp..core::cmp::min(xp + b, dp.xpix) {
+                    let idx = y * dp.xpix + x;
+                    buff[idx] = n;
+                    interior[idx] = int;
+                    orbit[idx] = orb;
+                }
+            }
+        }
     }
 }
 
+/**
+Exported function to find how many rows tall an exponential-map strip
+`x_pixels` wide has to be to cover distances from `r_max` down to `r_min`
+(see `render_exp_strip()`).
+*/
+#[no_mangle]
+pub extern fn exp_strip_height(x_pixels: usize, r_min: f64, r_max: f64) -> usize {
+    expmap::strip_height(x_pixels, r_min, r_max)
+}
+
+/**
+Exported function to draw an exponential map (a log-polar strip; see the
+`expmap` module) around the point `re` + `im`i into `IMAGE`. Going across
+the strip's `x_pixels` goes once around the point, counterclockwise from
+the positive real direction; going down it, the distance from the point
+shrinks from `r_max` to `r_min` (the strip is `exp_strip_height()` rows
+tall). Frames of a zoom into the point can then be made from it with
+`reproject_strip()`, far faster than by drawing each one.
+
+The view matrix and warp are ignored (and left alone). Returns `false` (and
+draws nothing) if the distances don't make sense or the strip won't fit
+under the memory cap.
+
+# Safety
+
+The same as `redraw()`'s.
+*/
+#[no_mangle]
+pub unsafe extern fn render_exp_strip(
+    x_pixels: usize,
+    re: f64, im: f64,
+    r_min: f64, r_max: f64,
+    use_polynomial_iterator: bool,
+    smooth: f32,
+) -> bool {
+    let y_pixels = expmap::strip_height(x_pixels, r_min, r_max);
+    if y_pixels == 0 { return false; }
+    let view = View {
+        re: Dd::from(0.0), im: Dd::from(fmath::ln(r_max)), rest: NO_REST,
+        width: 2.0 * core::f64::consts::PI,
+        matrix: IDENTITY, warp: Warp::LogPolar(Cx { re, im }),
+    };
+    render(&Tile::whole(x_pixels, y_pixels), &view, use_polynomial_iterator, smooth)
+}
+
+/**
+Exported function to make one frame of a zoom out of the exponential-map
+strip in `IMAGE` (put there by `render_exp_strip()`, or written back in
+with `reserve_image()`). `strip_x_pixels` and `strip_y_pixels` are the
+strip's size and `r_max` the distance at its top. The frame is
+`x_pixels` by `y_pixels`, centered on the strip's center, and as wide on
+the plane as a `redraw_centered()` image with the given `zoom`; anything in
+it outside the strip's range of distances gets the default color.
+
+The frame is written into `IMAGE` right after the strip, which is left as
+it is, so this can be called over and over. Returns where the frame is, or
+a null pointer if it won't fit under the memory cap.
+
+# Safety
+
+Reads and writes `IMAGE` (and can grow the per-pixel buffers); see the
+crate docs.
+*/
+#[no_mangle]
+pub unsafe extern fn reproject_strip(
+    strip_x_pixels: usize, strip_y_pixels: usize,
+    r_max: f64,
+    x_pixels: usize, y_pixels: usize,
+    zoom: f64,
+) -> *mut u32 {
+    let strip_len = match strip_x_pixels.checked_mul(strip_y_pixels) {
+        Some(n) if n > 0 => n,
+        _ => { return core::ptr::null_mut(); },
+    };
+    let frame_len = match x_pixels.checked_mul(y_pixels) {
+        Some(n) => n,
+        None => { return core::ptr::null_mut(); },
+    };
+    let total = match strip_len.checked_add(frame_len) {
+        Some(n) => n,
+        None => { return core::ptr::null_mut(); },
+    };
+    if !buffers().reserve(total) { return core::ptr::null_mut(); }
+    
+    let (strip, frame) = buffers().image().split_at_mut(strip_len);
+    let strip = expmap::Strip {
+        pixels: strip, width: strip_x_pixels, height: strip_y_pixels, r_max,
+    };
+    expmap::reproject(
+        &strip, frame, x_pixels, y_pixels, CENTERED_WIDTH / zoom / 2.0,
+        DEFAULT_COLOR
+    );
+    frame.as_mut_ptr()
+}
+
 /**
 Exported function to rewrite the `IMAGE` data after having changed the
-color gradients via calls to  `set_gradient()` and `set_n_gradients()`.
+color gradients via calls to  `set_gradient()` and `set_n_gradients()`
+(or their interior counterparts, or the interior metric).
+
+It does nothing while a render is under way (see `render_begin()`), which
+carries on with the color maps it started with; call it again once
+`render_done()` says so, or after `render_cancel()`.
+
+# Safety
+
+Reads the color settings and rewrites the per-pixel buffers; see the
+crate docs.
 */
 #[no_mangle]
 pub unsafe extern fn recolor() {
-    if DRAW_PARAMS.colormap_length < CURRENT_COLORMAP_LENGTH {
+    // Nothing's been drawn yet, or it's still being drawn.
+    if buffers().capacity() < DRAW_PARAMS.xpix * DRAW_PARAMS.ypix
+        || DRAW_PARAMS.block == 0 || DRAW_PARAMS.rendering
+    { return; }
+    
+    if TEXTURE_AXIS != TextureAxis::Off && TEXTURE_AXIS != DRAW_PARAMS.texture_axis {
+        DRAW_PARAMS.interior_metric = INTERIOR_METRIC;
+        DRAW_PARAMS.texture_axis = TEXTURE_AXIS;
+        let all_rows = 0..DRAW_PARAMS.ypix;
+        iterate(draw_params(), Grid::ALL, all_rows, buffers().pixels(),
+                buffers().image(), MapLengths::current(), references());
+        color_image();
+        return;
+    }
+    
+    let grew = DRAW_PARAMS.colormap_length < CURRENT_COLORMAP_LENGTH;
+    let interior_stale = CURRENT_INTERIOR_MAP_LENGTH > 0 && (
+        DRAW_PARAMS.colormap_length != CURRENT_COLORMAP_LENGTH
+        || DRAW_PARAMS.interior_metric != INTERIOR_METRIC
+        || !DRAW_PARAMS.interior_exact
+    );
+    if grew || interior_stale {
         reiterate(
-            &DRAW_PARAMS, &mut ITERMAP, CURRENT_COLORMAP_LENGTH,
-            &COEFFS, N_COEFFS
+            draw_params(), buffers().pixels(), CURRENT_COLORMAP_LENGTH,
+            INTERIOR_METRIC, references()
         );
+        DRAW_PARAMS.interior_metric = INTERIOR_METRIC;
+        DRAW_PARAMS.interior_exact = true;
     }
     DRAW_PARAMS.colormap_length = CURRENT_COLORMAP_LENGTH;
-    color_itermap(
-        &ITERMAP, &COLOR_MAP, &mut IMAGE, DEFAULT_COLOR,
-        DRAW_PARAMS.xpix * DRAW_PARAMS.ypix, CURRENT_COLORMAP_LENGTH
-    );
-    if let Some(f) = DRAW_PARAMS.smooth_frac {
-        smooth_image(&DRAW_PARAMS, f, &mut IMAGE);
+    color_image();
+}
+
+/**
+Color the current `ITERMAP` (and friends) into `IMAGE` with the current
+color settings, then smooth it if that's called for. This is the last step
+of both `redraw()` and `recolor()`.
+*/
+unsafe fn color_image() {
+    let (dp, buffers) = (draw_params(), buffers());
+    let texture = Texture {
+        texels: &*core::ptr::addr_of!(TEXTURE),
+        orbit: buffers.orbit(),
+        width: TEXTURE_WIDTH,
+        height: TEXTURE_HEIGHT,
+        period: TEXTURE_PERIOD,
+    };
+    let use_texture = TEXTURE_AXIS != TextureAxis::Off
+                      && dp.texture_axis == TEXTURE_AXIS;
+    let xpix = dp.xpix;
+    let (itermap, interior) = (&*buffers.itermap(), &*buffers.interior());
+    let colors = Colors {
+        colormap: &*core::ptr::addr_of!(COLOR_MAP),
+        n_shades: CURRENT_COLORMAP_LENGTH,
+        interior_map: &*core::ptr::addr_of!(INTERIOR_MAP),
+        n_interior_shades: CURRENT_INTERIOR_MAP_LENGTH,
+        default_color: DEFAULT_COLOR,
+    };
+    bands::in_bands(xpix, dp.ypix, buffers.image(), |rows, image| {
+        let start = rows.start * xpix;
+        let texture = texture.starting_at(start);
+        color_itermap(
+            &itermap[start..], &interior[start..], image, &colors,
+            if use_texture { Some(&texture) } else { None }
+        );
+    });
+    if let Some(f) = dp.smooth_frac {
+        smooth_image(dp, f, buffers.image());
+    }
+    trim_apron(dp, buffers.image());
+}
+
+/**
+Drop the apron columns and rows (see `DrawParams`) from the `IMAGE` data in
+`buff`, so it's just the tile, `xpix - apron_x` pixels to a row.
+*/
+fn trim_apron(dp: &DrawParams, buff: &mut [u32]) {
+    if dp.apron_x == 0 { return; }
+    let out_xpix = dp.xpix - dp.apron_x;
+    for y in 1..(dp.ypix - dp.apron_y) {
+        let src = y * dp.xpix;
+        buff.copy_within(src..(src + out_xpix), y * out_xpix);
     }
 }
 
@@ -726,4 +3112,398 @@ fn dbg_float(x: f64) {
         dbg_msg("f+"); dbg_num(n);
     }
 }
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+    Hold this while using any of the `static`s (the per-pixel buffers, on
+    native builds, all share one pool), since tests run side by side.
+    */
+    pub fn lock() -> std::sync::MutexGuard<'static, ()> {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether two results are exactly the same.
+    pub fn same_result(a: &IterResult, b: &IterResult) -> bool {
+        a.n == b.n && a.interior == b.interior && a.orbit == b.orbit
+            && a.state.z == b.state.z
+            && a.state.min_sqmod.to_bits() == b.state.min_sqmod.to_bits()
+    }
+
+    /**
+    Draw an `XPIX` x `YPIX` image in single and then double precision, and
+    return how many pixels' iteration counts differ, and how many of those
+    have neighbors that all agree with each other in double precision.
+    */
+    unsafe fn single_vs_double(
+        (x, y, width): (f64, f64, f64),
+        use_poly: bool,
+    ) -> (usize, usize) {
+        const XPIX: usize = 600;
+        const YPIX: usize = 400;
+        let counts = [1, 2].map(|p| {
+            set_precision(p);
+            assert!(redraw(XPIX, YPIX, x, y, width, use_poly, 0.0));
+            buffers().itermap()[..XPIX * YPIX].to_vec()
+        });
+        set_precision(0);
+
+        let [single, double] = &counts;
+        let (mut differ, mut flat) = (0, 0);
+        for yp in 1..(YPIX - 1) {
+            for xp in 1..(XPIX - 1) {
+                let idx = yp * XPIX + xp;
+                if single[idx] == double[idx] { continue; }
+                differ += 1;
+                let n = double[idx - XPIX - 1];
+                let around = [
+                    idx - XPIX - 1, idx - XPIX, idx - XPIX + 1, idx - 1,
+                    idx + 1, idx + XPIX - 1, idx + XPIX, idx + XPIX + 1,
+                ];
+                if around.iter().all(|&i| double[i] == n) { flat += 1; }
+            }
+        }
+        (differ, flat)
+    }
+
+    /// The precision a 600 x 400 image of the view gets, if it isn't forced.
+    unsafe fn picked((x, y, width): (f64, f64, f64), use_poly: bool) -> Precision {
+        set_precision(0);
+        let view = View::current(Dd::from(x), Dd::from(y), NO_REST, width);
+        set_draw_params(&Tile::whole(600, 400), (0, 0), &view, use_poly, None);
+        draw_params().precision()
+    }
+
+    #[test]
+    fn single_agrees_with_double() {
+        let _guard = lock();
+        unsafe {
+            load_preset(1, false);
+            update_color_map();
+            let n = 600 * 400;
+
+            // The whole set, and views of its edge as small as single precision goes.
+            for view in [
+                (-2.0, 1.0, 3.0), (-1.16, 0.87, 1.2), (-0.725, 1.05, 1.2), (-0.35, 0.9, 1.2),
+            ] {
+                assert!(picked(view, false) == Precision::Single, "{:?}", view);
+                let (differ, flat) = single_vs_double(view, false);
+                assert!(differ * 100 <= n, "{} differ in {:?}", differ, view);
+                assert!(flat * 1_000_000 < 15 * n, "{} flat in {:?}", flat, view);
+            }
+            assert!(picked((-0.35, 0.9, 1.1), false) == Precision::Double);
+
+            set_coeff(0, -0.1, 0.65);
+            set_coeff(1, 0.0, 0.0);
+            set_coeff(2, 1.0, 0.0);
+            set_n_coeffs(3);
+            assert!(picked((-1.5, 1.0, 3.0), true) == Precision::Double);
+            let (differ, flat) = single_vs_double((-1.5, 1.0, 3.0), true);
+            assert!(differ * 100 <= 6 * n, "{} differ", differ);
+            assert!(flat * 1_000_000 < 5 * n, "{} flat", flat);
+        }
+    }
+
+    /**
+    `redraw()`, after a one-pixel image, so that nothing gets carried over
+    from the last image. Returns the `ITERMAP`, `INTERIOR` and `ORBIT` data.
+    */
+    unsafe fn redraw_afresh(
+        xpix: usize, ypix: usize,
+        (x, y, width): (f64, f64, f64),
+        use_poly: bool,
+    ) -> (Vec<u16>, Vec<u16>, Vec<u32>) {
+        assert!(redraw(1, 1, x, y, width, use_poly, 0.0));
+        assert!(redraw(xpix, ypix, x, y, width, use_poly, 0.0));
+        let (b, n) = (buffers(), xpix * ypix);
+        (b.itermap()[..n].to_vec(), b.interior()[..n].to_vec(), b.orbit()[..n].to_vec())
+    }
+
+    /**
+    Draw an `xpix` x `ypix` image of `view` by brute force and then with
+    `strategy`, with nothing to keep the latter from filling in all it can
+    (no texture, no interior color map), and return both `ITERMAP`s. The
+    view shouldn't be symmetric, or half of it gets mirrored instead.
+    */
+    pub unsafe fn brute_force_and(
+        strategy: u8,
+        xpix: usize, ypix: usize,
+        view: (f64, f64, f64),
+        use_poly: bool,
+    ) -> [Vec<u16>; 2] {
+        set_n_interior_gradients(0);
+        update_interior_map();
+        set_texture_axis(0);
+        let maps = [0, strategy].map(|s| {
+            set_strategy(s);
+            redraw_afresh(xpix, ypix, view, use_poly).0
+        });
+        set_strategy(0);
+        maps
+    }
+
+    #[test]
+    fn mandelbrot_ignores_coefficients() {
+        let _guard = lock();
+        unsafe {
+            load_preset(1, false);
+            update_color_map();
+            set_precision(1);
+            set_strategy(1);
+            let [with, without] = [3, 0].map(|n| {
+                set_n_coeffs(n);
+                redraw_afresh(300, 200, (-0.8, 0.2, 0.1), false)
+            });
+            set_precision(0);
+            set_strategy(0);
+            assert!(with == without);
+        }
+    }
+
+    /**
+    Draw the same images with their rows in one band and in a dozen, and
+    check that they come out the same, whatever the strategy and precision.
+    */
+    #[cfg(feature = "threads")]
+    #[test]
+    fn bands_change_nothing() {
+        const XPIX: usize = 160;
+        const YPIX: usize = 100;
+        let _guard = lock();
+        unsafe {
+            load_preset(6, false);
+            update_color_map();
+            // So that subdivision and boundary tracing fill in all they can.
+            set_n_interior_gradients(0);
+            update_interior_map();
+            set_texture_axis(0);
+            set_coeff(0, -0.1, 0.65);
+            set_coeff(1, 0.0, 0.0);
+            set_coeff(2, 1.0, 0.0);
+            set_n_coeffs(3);
+            for strategy in [0, 1, 2] {
+                set_strategy(strategy);
+                for precision in 0..=4 {
+                    set_precision(precision);
+                    for (use_poly, view) in [(false, (-0.8, 0.2, 0.1)), (true, (-0.3, 0.3, 0.4))] {
+                        let [one, many] = [1, 3].map(|threads| {
+                            bands::THREADS.with(|t| t.set(Some(threads)));
+                            redraw_afresh(XPIX, YPIX, view, use_poly)
+                        });
+                        assert!(one == many, "{:?}", (strategy, precision, use_poly));
+                    }
+                }
+            }
+            bands::THREADS.with(|t| t.set(None));
+            set_strategy(0);
+            set_precision(0);
+        }
+    }
+
+    /**
+    Points that don't escape get their colors from `INTERIOR_MAP` by their
+    `INTERIOR` values, in more than a few shades, and `recolor()` switches
+    between that and `DEFAULT_COLOR` without redrawing.
+    */
+    #[test]
+    fn interior_shading() {
+        const XPIX: usize = 300;
+        const YPIX: usize = 200;
+        let _guard = lock();
+        unsafe {
+            load_preset(1, false);
+            update_color_map();
+            load_preset(0, true);
+            update_interior_map();
+            set_interior_metric(false);
+            set_texture_axis(0);
+            let (itermap, interior, _) = redraw_afresh(XPIX, YPIX, (-2.0, 1.0, 3.0), false);
+            let shaded = buffers().image()[..XPIX * YPIX].to_vec();
+
+            let (map, len) = (&*core::ptr::addr_of!(INTERIOR_MAP), CURRENT_INTERIOR_MAP_LENGTH);
+            let captive = |idx: &usize| itermap[*idx] as usize >= CURRENT_COLORMAP_LENGTH;
+            let mut shades = std::collections::BTreeSet::new();
+            for idx in (0..XPIX * YPIX).filter(captive) {
+                let want = map[((interior[idx] as usize) * len) >> 16];
+                assert_eq!(shaded[idx], want, "pixel {}", idx);
+                shades.insert(want);
+            }
+            assert!(shades.len() > 20, "{} shades", shades.len());
+
+            set_n_interior_gradients(0);
+            update_interior_map();
+            recolor();
+            let default = DEFAULT_COLOR;
+            for idx in (0..XPIX * YPIX).filter(captive) {
+                assert_eq!(buffers().image()[idx], default, "pixel {}", idx);
+            }
+
+            load_preset(0, true);
+            update_interior_map();
+            recolor();
+            assert!(buffers().image()[..XPIX * YPIX] == shaded[..]);
+            set_n_interior_gradients(0);
+            update_interior_map();
+        }
+    }
+
+    /// Even one- and two-color random palettes make a color map.
+    #[test]
+    fn short_random_palettes() {
+        let _guard = lock();
+        unsafe {
+            for style in 0..4 {
+                for n in [1, 2] {
+                    random_palette(7, style, n, 100, false);
+                    update_color_map();
+                    random_palette(7, style, n, 100, true);
+                    update_interior_map();
+                    let lengths = MapLengths::current();
+                    assert_eq!((lengths.colors, lengths.interior), (n * 100, n * 100),
+                               "style {}", style);
+                }
+            }
+            set_n_interior_gradients(0);
+            update_interior_map();
+        }
+    }
+
+    /// Every preset loads whole, and fits in the color map with room to spare.
+    #[test]
+    fn presets_fit() {
+        let _guard = lock();
+        unsafe {
+            for n in 0..n_presets() {
+                load_preset(n, false);
+                update_color_map();
+                load_preset(n, true);
+                update_interior_map();
+                let (gradients, interior_gradients) = (N_GRADIENTS, N_INTERIOR_GRADIENTS);
+                assert_eq!((gradients, interior_gradients), (preset_len(n), preset_len(n)));
+                let shades = (0..preset_len(n)).map(|i| preset_shades(n, i) as usize).sum();
+                let lengths = MapLengths::current();
+                assert_eq!((lengths.colors, lengths.interior), (shades, shades), "preset {}", n);
+                assert!(shades > 0 && shades < COLOR_MAP_LENGTH, "preset {}", n);
+            }
+            set_n_interior_gradients(0);
+            update_interior_map();
+        }
+    }
+
+    /// Tiles, aprons and all, paste together into exactly the whole image.
+    #[test]
+    fn tiles_match_whole() {
+        const XPIX: usize = 151;
+        const YPIX: usize = 101;
+        let _guard = lock();
+        unsafe {
+            load_preset(1, false);
+            update_color_map();
+            set_coeff(0, -0.1, 0.65);
+            set_coeff(1, 0.0, 0.0);
+            set_coeff(2, 1.0, 0.0);
+            set_n_coeffs(3);
+            for (use_poly, (x, y, width)) in [(false, (-2.0, 1.0, 3.0)), (true, (-1.5, 1.0, 3.0))] {
+                assert!(redraw(XPIX, YPIX, x, y, width, use_poly, 0.5));
+                let whole = buffers().image()[..XPIX * YPIX].to_vec();
+                for (x0, x1) in [(0, 75), (75, XPIX)] {
+                    for (y0, y1) in [(0, 50), (50, YPIX)] {
+                        let (w, h) = (x1 - x0, y1 - y0);
+                        assert!(render_tile(XPIX, YPIX, x0, y0, w, h, x, y, width, use_poly, 0.5));
+                        let tile = &buffers().image()[..w * h];
+                        for row in 0..h {
+                            let start = (y0 + row) * XPIX + x0;
+                            assert!(tile[row * w..(row + 1) * w] == whole[start..start + w],
+                                    "row {} of the tile at ({}, {})", row, x0, y0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// `exact_view()` for the given center and zoom, put in `COORDS` first.
+    unsafe fn view_of(xpix: usize, ypix: usize, re: &str, im: &str, zoom: &str) -> Option<View> {
+        let coords = &mut *core::ptr::addr_of_mut!(COORDS);
+        let mut end = 0;
+        for part in [re, im, zoom] {
+            coords[end..end + part.len()].copy_from_slice(part.as_bytes());
+            end += part.len();
+        }
+        exact_view(xpix, ypix, re.len(), im.len(), zoom.len())
+    }
+
+    #[test]
+    fn exact_views() {
+        let _guard = lock();
+        unsafe {
+            VIEW_MATRIX = IDENTITY;
+            let v = view_of(400, 300, "-0.75", "0.1", "2").unwrap();
+            assert_eq!((v.re.hi, v.re.lo, v.width), (-1.75, 0.0, 2.0));
+            assert!((v.im.hi - 0.85).abs() < 1e-16 && v.im.lo.abs() < 1e-16);
+
+            // Deep down, nothing gets lost between the double-double and the rest.
+            let (re, im) = ("-0.74364388703715870475219150611477", "0.13182590420531197049313205638514");
+            let v = view_of(600, 400, re, im, "1.5e40").unwrap();
+            assert_eq!(v.width, 4.0 / 1.5e40);
+            let parts = |d: Dd, rest: &Fixed| {
+                Fixed::from_f64(d.hi, fixed::MAX_LIMBS).add(&Fixed::from_f64(d.lo, fixed::MAX_LIMBS)).add(rest)
+            };
+            let center = |s: &str| Fixed::parse(s.as_bytes(), fixed::MAX_LIMBS).unwrap();
+            let half = |x: f64| Fixed::from_f64(x / 2.0, fixed::MAX_LIMBS);
+            let want_re = center(re).sub(&half(v.width));
+            let want_im = center(im).add(&half(v.width * 400.0 / 600.0));
+            assert_eq!(parts(v.re, &v.rest[0]).sub(&want_re).to_f64(), 0.0);
+            assert_eq!(parts(v.im, &v.rest[1]).sub(&want_im).to_f64(), 0.0);
+            assert!(v.rest[0].to_f64() != 0.0 && v.rest[0].to_f64().abs() < 1e-32);
+
+            for (re, im, zoom) in [
+                ("", "0", "1"), ("0", "", "1"), ("0", "0", ""),
+                (".", "0", "1"), ("1.2.3", "0", "1"), ("e5", "0", "1"), ("-", "0", "1"),
+                ("4294967296", "0", "1"), ("0", "1e100000", "1"),
+                ("0", "0", "0"), ("0", "0", "-1"), ("0", "0", "inf"), ("0", "0", "NaN"),
+                ("0", "0", "1e999"), ("0", "0", "1e-999"), ("0", "0", "x"),
+            ] {
+                assert!(view_of(400, 300, re, im, zoom).is_none(), "{:?}", (re, im, zoom));
+            }
+            assert!(view_of(0, 300, "0", "0", "1").is_none());
+            let v = view_of(400, 300, "+3", "-1e-99999", "1").unwrap();
+            assert_eq!((v.re.hi, v.im.hi), (1.0, 1.5));
+
+            // Up to COORDS_LENGTH bytes in all.
+            let long = format!("-0.{}1", "0".repeat(COORDS_LENGTH - 11));
+            let v = view_of(400, 300, &long, "0.5", "1e02").unwrap();
+            assert_eq!((v.re.hi, v.width), (-0.02, 0.04));
+            assert!(exact_view(400, 300, long.len(), 3, 5).is_none());
+            for (re_len, im_len, zoom_len) in [
+                (COORDS_LENGTH, 1, 1), (4000, 90, 7), (1, 1, COORDS_LENGTH),
+                (usize::MAX, 1, 1), (1, usize::MAX, 1), (1, 1, usize::MAX),
+            ] {
+                assert!(exact_view(400, 300, re_len, im_len, zoom_len).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn resumed_orbits_match() {
+        let points = [(-0.1, 0.2), (-1.0, 0.05), (0.3, 0.5), (-0.75, 0.1), (0.26, 0.0)];
+        for axis in [TextureAxis::Off, TextureAxis::Trap] {
+            for metric in [InteriorMetric::MinModulus, InteriorMetric::FinalModulus] {
+                let limits = Limits { sq_mod_limit: SQ_MOD_LIMIT, iter_limit: 400, metric, axis };
+                for (x, y) in points {
+                    let whole = mandelbrot_iter(x, y, &limits);
+                    for n0 in [0, 1, 10, 57, 400] {
+                        let part = mandelbrot_iter(x, y, &Limits { iter_limit: n0, ..limits });
+                        // Only orbits that haven't diverged yet carry on.
+                        if part.n < n0 { continue; }
+                        let rest = mandelbrot_iter_from(x, y, n0, &part.state, &limits);
+                        assert!(same_result(&whole, &rest), "({}, {}) from {}", x, y, n0);
+                    }
+                }
+            }
+        }
+    }
+}
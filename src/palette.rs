@@ -0,0 +1,444 @@
+/*!
+Generating color schemes, as opposed to just turning them into color maps.
+
+Everything in here produces a list of color _stops_; the gradients of a
+color scheme go from each stop to the next one, and the last one wraps back
+around to the first, so the color map cycles smoothly.
+*/
+
+use crate::fmath;
+
+/// A plain old 24-bit color.
+#[derive(Clone, Copy)]
+pub struct Rgb { pub r: u8, pub g: u8, pub b: u8 }
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb { r: 0, g: 0, b: 0 };
+
+    /// From channel values in the range [0, 1]; out-of-range values clamp.
+    fn from_unit(r: f64, g: f64, b: f64) -> Rgb {
+        fn chan(x: f64) -> u8 {
+            if x.is_nan() || x <= 0.0 { 0 }
+            else if x >= 1.0 { 255 }
+            else { (x * 255.0 + 0.5) as u8 }
+        }
+        Rgb { r: chan(r), g: chan(g), b: chan(b) }
+    }
+
+    /**
+    From hue (in turns, so 0.0 and 1.0 are both red), saturation, and
+    lightness, the latter two in [0, 1].
+    */
+    fn from_hsl(h: f64, s: f64, l: f64) -> Rgb {
+        let h = h - fmath::floor(h);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let hp = h * 6.0;
+        let x = c * (1.0 - ((hp - 2.0 * fmath::floor(hp / 2.0)) - 1.0).abs());
+        let (r, g, b) = match hp as u8 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        Rgb::from_unit(r + m, g + m, b + m)
+    }
+}
+
+/**
+SplitMix64. It's tiny, it's only integer arithmetic, and it's well-mixed even
+for consecutive seeds, so the same seed gives the same palette everywhere.
+*/
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng { Rng(seed) }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64) / ((1u64 << 53) as f64)
+    }
+
+    /// Uniform in [`lo`, `hi`).
+    fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * self.next_f64()
+    }
+}
+
+/// The kinds of procedural palettes `generate()` knows how to make.
+#[derive(Clone, Copy)]
+pub enum PaletteStyle {
+    /// a + b·cos(2π(c·t + d)), separately for each channel
+    Cosine,
+    /// hues within a narrow band, alternating dark and light
+    Analogous,
+    /// alternating between a hue and its complement
+    Complementary,
+    /// a single hue, ramping from dark to light and back
+    Monochrome,
+}
+
+impl PaletteStyle {
+    /// Anything unrecognized is `Cosine`.
+    pub fn from_u8(n: u8) -> PaletteStyle {
+        match n {
+            1 => PaletteStyle::Analogous,
+            2 => PaletteStyle::Complementary,
+            3 => PaletteStyle::Monochrome,
+            _ => PaletteStyle::Cosine,
+        }
+    }
+}
+
+/**
+Fill `stops` with a palette of the given `style`, determined entirely by
+`seed`.
+*/
+pub fn generate(seed: u64, style: PaletteStyle, stops: &mut [Rgb]) {
+    let mut rng = Rng::new(seed);
+    let n = stops.len() as f64;
+
+    match style {
+        PaletteStyle::Cosine => {
+            // Per channel: offset `a`, amplitude `b` (small enough to stay
+            // in gamut), whole-number frequency `c` (so the palette still
+            // cycles), and phase `d`.
+            let mut a = [0.0f64; 3];
+            let mut b = [0.0f64; 3];
+            let mut c = [0.0f64; 3];
+            let mut d = [0.0f64; 3];
+            for ch in 0..3 {
+                a[ch] = rng.range(0.3, 0.7);
+                let b_max = if a[ch] < 0.5 { a[ch] } else { 1.0 - a[ch] };
+                b[ch] = rng.range(0.5 * b_max, b_max);
+                c[ch] = if rng.next_u64().is_multiple_of(4) { 2.0 } else { 1.0 };
+                d[ch] = rng.next_f64();
+            }
+            for (i, stop) in stops.iter_mut().enumerate() {
+                let t = (i as f64) / n;
+                let mut v = [0.0f64; 3];
+                for ch in 0..3 {
+                    let arg = 2.0 * core::f64::consts::PI * (c[ch] * t + d[ch]);
+                    v[ch] = a[ch] + b[ch] * fmath::cos(arg);
+                }
+                *stop = Rgb::from_unit(v[0], v[1], v[2]);
+            }
+        },
+        PaletteStyle::Analogous => {
+            let hue = rng.next_f64();
+            let spread = rng.range(0.05, 0.125);
+            let sat = rng.range(0.5, 0.9);
+            for (i, stop) in stops.iter_mut().enumerate() {
+                let t = (i as f64) / n;
+                let h = hue + spread * fmath::cos(2.0 * core::f64::consts::PI * t);
+                let l = if i % 2 == 0 { rng.range(0.1, 0.35) }
+                        else { rng.range(0.55, 0.85) };
+                *stop = Rgb::from_hsl(h, sat, l);
+            }
+        },
+        PaletteStyle::Complementary => {
+            let hue = rng.next_f64();
+            for (i, stop) in stops.iter_mut().enumerate() {
+                let side = if i % 2 == 0 { 0.0 } else { 0.5 };
+                let h = hue + side + rng.range(-0.04, 0.04);
+                let s = rng.range(0.5, 0.95);
+                let l = rng.range(0.25, 0.75);
+                *stop = Rgb::from_hsl(h, s, l);
+            }
+        },
+        PaletteStyle::Monochrome => {
+            let hue = rng.next_f64();
+            let sat = rng.range(0.2, 0.7);
+            for (i, stop) in stops.iter_mut().enumerate() {
+                let t = (i as f64) / n;
+                let l = 0.05 + 0.9 * (1.0 - (2.0 * t - 1.0).abs());
+                *stop = Rgb::from_hsl(hue, sat, l);
+            }
+        },
+    }
+}
+
+/// One color stop of a preset, and how many shades to take to the next one.
+pub struct Stop { pub color: Rgb, pub shades: u16 }
+
+/// A named palette that's compiled into the module.
+pub struct Preset { pub name: &'static str, pub stops: &'static [Stop] }
+
+const fn stop(rgb: u32, shades: u16) -> Stop {
+    Stop {
+        color: Rgb {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        },
+        shades,
+    }
+}
+
+/**
+The built-in palettes. The first one is the default color scheme of the web
+front end; the rest are roughly in order of tastefulness.
+*/
+pub static PRESETS: [Preset; 8] = [
+    Preset { name: "grayscale", stops: &[
+        stop(0x000000, 128), stop(0xffffff, 256),
+    ]},
+    Preset { name: "classic", stops: &[
+        stop(0x000764,  82), stop(0x206bcb, 133), stop(0xedffff, 114),
+        stop(0xffaa00, 110), stop(0x000200,  73),
+    ]},
+    Preset { name: "fire", stops: &[
+        stop(0x000000,  64), stop(0x5c0000,  96), stop(0xc81e00, 96),
+        stop(0xff8c00, 128), stop(0xffe650, 128), stop(0xffffff, 96),
+    ]},
+    Preset { name: "ocean", stops: &[
+        stop(0x000814,  96), stop(0x002a5c, 128), stop(0x0077b6, 128),
+        stop(0x00b4d8, 128), stop(0x90e0ef,  96), stop(0xf0fbff, 96),
+    ]},
+    Preset { name: "viridis", stops: &[
+        stop(0x440154, 48), stop(0x482878, 48), stop(0x3e4989, 48),
+        stop(0x31688e, 48), stop(0x26828e, 48), stop(0x1f9e89, 48),
+        stop(0x35b779, 48), stop(0x6ece58, 48), stop(0xb5de2b, 48),
+        stop(0xfde725, 96),
+    ]},
+    Preset { name: "magma", stops: &[
+        stop(0x000004, 48), stop(0x1c1044, 48), stop(0x4f127b, 48),
+        stop(0x812581, 48), stop(0xb5367a, 48), stop(0xe55064, 48),
+        stop(0xfb8761, 48), stop(0xfec287, 48), stop(0xfcfdbf, 96),
+    ]},
+    Preset { name: "psychedelic", stops: &[
+        stop(0xff0000, 32), stop(0xffff00, 32), stop(0x00ff00, 32),
+        stop(0x00ffff, 32), stop(0x0000ff, 32), stop(0xff00ff, 32),
+    ]},
+    Preset { name: "midnight", stops: &[
+        stop(0x000000, 256), stop(0x0000ff, 256), stop(0x00ffff, 256),
+        stop(0xffffff, 256),
+    ]},
+];
+
+/// A color in Oklab space: perceptual lightness and two opponent axes.
+#[derive(Clone, Copy)]
+struct Lab { l: f32, a: f32, b: f32 }
+
+impl Lab {
+    fn sq_dist(&self, other: &Lab) -> f32 {
+        let dl = self.l - other.l;
+        let da = self.a - other.a;
+        let db = self.b - other.b;
+        dl * dl + da * da + db * db
+    }
+
+    /// From linear-light RGB channel values in [0, 1].
+    fn from_linear(r: f64, g: f64, b: f64) -> Lab {
+        let l = fmath::cbrt(0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b);
+        let m = fmath::cbrt(0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b);
+        let s = fmath::cbrt(0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b);
+        Lab {
+            l: (0.210_454_255_3 * l + 0.793_617_785_0 * m - 0.004_072_046_8 * s) as f32,
+            a: (1.977_998_495_1 * l - 2.428_592_205_0 * m + 0.450_593_709_9 * s) as f32,
+            b: (0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766_0 * s) as f32,
+        }
+    }
+
+    fn to_rgb(self) -> Rgb {
+        let (lc, ac, bc) = (self.l as f64, self.a as f64, self.b as f64);
+        let l = lc + 0.396_337_777_4 * ac + 0.215_803_757_3 * bc;
+        let m = lc - 0.105_561_345_8 * ac - 0.063_854_172_8 * bc;
+        let s = lc - 0.089_484_177_5 * ac - 1.291_485_548_0 * bc;
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+        let r =  4.076_741_662_1 * l - 3.307_711_591_3 * m + 0.230_969_929_2 * s;
+        let g = -1.268_438_004_6 * l + 2.609_757_401_1 * m - 0.341_319_396_5 * s;
+        let b = -0.004_196_086_3 * l - 0.703_418_614_7 * m + 1.707_614_701_0 * s;
+        Rgb::from_unit(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.040_45 { c / 12.92 }
+    else { fmath::powf((c + 0.055) / 1.055, 2.4) }
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.003_130_8 { 12.92 * c }
+    else { 1.055 * fmath::powf(c, 1.0 / 2.4) - 0.055 }
+}
+
+/// Most pixels of a reference image that get looked at by `extract()`.
+const MAX_SAMPLES: usize = 4096;
+/// Most colors `extract()` will pick out.
+const MAX_CLUSTERS: usize = 32;
+/// Most rounds of k-means refinement `extract()` will do.
+const MAX_ROUNDS: usize = 24;
+
+/**
+Pick out the `stops.len()` most representative colors in `pixels` (in the
+same `0xAABBGGRR` format as `IMAGE`) and fill `stops` with them, ordered so
+each is close to the next. Returns how many stops were actually filled,
+which can be fewer if the image doesn't have that many distinct colors (or
+any opaque pixels at all).
+
+This is k-means clustering in Oklab space on an evenly-spaced sample of the
+image, followed by a nearest-neighbor walk starting from the darkest color.
+*/
+pub fn extract(pixels: &[u32], stops: &mut [Rgb]) -> usize {
+    // Linearizing sRGB is the slow part, and there are only 256 values.
+    let mut linear = [0.0f64; 256];
+    for (n, v) in linear.iter_mut().enumerate() {
+        *v = srgb_to_linear((n as f64) / 255.0);
+    }
+
+    let mut samples = [Lab { l: 0.0, a: 0.0, b: 0.0 }; MAX_SAMPLES];
+    let stride = pixels.len() / MAX_SAMPLES + 1;
+    let mut n_samples = 0;
+    for &px in pixels.iter().step_by(stride) {
+        // Mostly-transparent pixels aren't part of the picture.
+        if (px >> 24) < 128 { continue; }
+        samples[n_samples] = Lab::from_linear(
+            linear[(px & 0xFF) as usize],
+            linear[((px >> 8) & 0xFF) as usize],
+            linear[((px >> 16) & 0xFF) as usize],
+        );
+        n_samples += 1;
+    }
+    let samples = &samples[..n_samples];
+    if samples.is_empty() { return 0; }
+
+    // k-means++ seeding, with a fixed seed so the same image always gives
+    // the same palette.
+    let mut k = if stops.len() < samples.len() { stops.len() } else { samples.len() };
+    if k > MAX_CLUSTERS { k = MAX_CLUSTERS; }
+    let mut rng = Rng::new(0);
+    let mut centers = [Lab { l: 0.0, a: 0.0, b: 0.0 }; MAX_CLUSTERS];
+    let mut nearest = [f32::INFINITY; MAX_SAMPLES];
+    centers[0] = samples[(rng.next_u64() % (samples.len() as u64)) as usize];
+    let mut n_centers = 1;
+    while n_centers < k {
+        let mut total = 0.0f64;
+        for (i, s) in samples.iter().enumerate() {
+            let d = s.sq_dist(&centers[n_centers - 1]);
+            if d < nearest[i] { nearest[i] = d; }
+            total += nearest[i] as f64;
+        }
+        // Every sample is already a center; there's nothing left to pick.
+        if total <= 0.0 { break; }
+        let mut target = rng.next_f64() * total;
+        let mut pick = samples.len() - 1;
+        for (i, &d) in nearest[..samples.len()].iter().enumerate() {
+            target -= d as f64;
+            if target < 0.0 { pick = i; break; }
+        }
+        centers[n_centers] = samples[pick];
+        n_centers += 1;
+    }
+    let centers = &mut centers[..n_centers];
+
+    // Lloyd's algorithm.
+    let mut assignment = [0u8; MAX_SAMPLES];
+    for round in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for (i, s) in samples.iter().enumerate() {
+            let mut best = 0;
+            let mut best_d = f32::INFINITY;
+            for (j, c) in centers.iter().enumerate() {
+                let d = s.sq_dist(c);
+                if d < best_d { best_d = d; best = j; }
+            }
+            if round == 0 || assignment[i] != best as u8 {
+                assignment[i] = best as u8;
+                changed = true;
+            }
+        }
+        if !changed { break; }
+
+        for (j, c) in centers.iter_mut().enumerate() {
+            let (mut l, mut a, mut b, mut count) = (0.0f64, 0.0f64, 0.0f64, 0usize);
+            for (i, s) in samples.iter().enumerate() {
+                if assignment[i] as usize == j {
+                    l += s.l as f64; a += s.a as f64; b += s.b as f64;
+                    count += 1;
+                }
+            }
+            // A center that loses all its samples just stays put.
+            if count > 0 {
+                let n = count as f64;
+                *c = Lab { l: (l / n) as f32, a: (a / n) as f32, b: (b / n) as f32 };
+            }
+        }
+    }
+
+    // Walk from the darkest color to whichever remaining one is nearest.
+    let mut darkest = 0;
+    for (j, c) in centers.iter().enumerate() {
+        if c.l < centers[darkest].l { darkest = j; }
+    }
+    centers.swap(0, darkest);
+    for j in 1..n_centers {
+        let mut best = j;
+        for m in (j + 1)..n_centers {
+            if centers[j - 1].sq_dist(&centers[m]) < centers[j - 1].sq_dist(&centers[best]) {
+                best = m;
+            }
+        }
+        centers.swap(j, best);
+    }
+
+    for (stop, c) in stops.iter_mut().zip(centers.iter()) {
+        *stop = c.to_rgb();
+    }
+    n_centers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels(stops: &[Rgb]) -> Vec<(u8, u8, u8)> {
+        stops.iter().map(|c| (c.r, c.g, c.b)).collect()
+    }
+
+    /// The same seed gives the same palette, and another seed a different one.
+    #[test]
+    fn same_seed_same_palette() {
+        for style in 0..4 {
+            let [a, b, c] = [42, 42, 43].map(|seed| {
+                let mut stops = [Rgb::BLACK; 7];
+                generate(seed, PaletteStyle::from_u8(style), &mut stops);
+                channels(&stops)
+            });
+            assert!(a == b, "style {}", style);
+            assert!(a != c, "style {}", style);
+        }
+    }
+
+    /**
+    A picture in two colors (and a transparent patch of a third) gives back
+    just those two, darker first.
+    */
+    #[test]
+    fn extracts_two_colors() {
+        let (dark, light) = (0xFF_40_20_10u32, 0xFF_90_E0_F0u32);
+        let mut pixels = vec![dark; 64 * 64];
+        for (i, px) in pixels.iter_mut().enumerate() {
+            if i % 64 >= 24 { *px = light; }
+            if i < 64 * 8 && i % 64 >= 56 { *px = 0x10_00_FF_00; }
+        }
+        let mut stops = [Rgb::BLACK; 4];
+        assert_eq!(extract(&pixels, &mut stops), 2);
+        let near = |c: &Rgb, px: u32| {
+            let want = [px & 0xFF, (px >> 8) & 0xFF, (px >> 16) & 0xFF];
+            [c.r, c.g, c.b].iter().zip(want).all(|(&got, want)| got.abs_diff(want as u8) <= 1)
+        };
+        assert!(near(&stops[0], dark) && near(&stops[1], light),
+                "{:?}", channels(&stops[..2]));
+    }
+}
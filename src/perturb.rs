@@ -0,0 +1,338 @@
+/*!
+Perturbation: iterating deep zooms at about the speed of `f64`.
+
+Deep in a zoom, the points of an image are all very close together, and so
+are their orbits, for a while at least. So one of them, the reference, gets
+iterated in as much precision as it takes (in `Fixed` point; see the
+`fixed` module), and every other point only as its difference from the
+reference, in plain `f64`. If the reference orbit is `Z` and a point's is
+`Z + δ`, then
+
+```text
+δ ← 2Zδ + δ² + δc
+```
+
+where `δc` is how far the point is from the reference point. The `δ`s are
+tiny, but `f64` keeps its relative precision all the way down to `1e-300`
+or so, and `Z` only needs to be right to `f64` precision too.
+
+That breaks down when a point's orbit passes much closer to 0 than the
+reference orbit does at the same iteration (`δ` is then most of `Z + δ`,
+and has lost the precision it needs), or when the point hasn't diverged by
+the time the reference has. Those points are _glitched_. Pauldelbrot's test
+catches the first kind: `|Z + δ|` gets smaller than `|Z|` by more than a
+factor of `GLITCH_TOLERANCE`. Glitched points are marked (see `glitched()`)
+instead of getting a result, and once a pass over the image is done,
+`fix_glitches()` takes one of them as a new reference, iterates the rest of
+them again from that, and keeps at it until none are left.
+
+Deeper still, every point takes thousands of iterations just to get
+anywhere, and for all of those, `δ` is very nearly a polynomial in `δc`:
+
+```text
+δ = Aδc + Bδc² + Cδc³
+A ← 2ZA + 1
+B ← 2ZB + A²
+C ← 2ZC + 2AB
+```
+
+So the reference works out `A`, `B`, and `C` along with its orbit, for as
+long as the last term stays much smaller than the first anywhere in the
+image (which is when the terms it leaves out are too small to matter), and
+every point starts from there (see `Reference::skip`). `B` and `C` are kept
+multiplied by the size of the image and its square, since on their own
+they'd overflow in the deepest zooms (and the terms' squares would
+underflow, so they get compared by the sums of the absolute values of
+their parts instead). The points' orbits don't get checked for
+glitches over the skipped iterations (the series doesn't glitch), and the
+closest they got to 0 there is taken to be the reference's.
+
+Only the Mandelbrot iterator is done this way, and the results differ from
+iterating each point in enough precision only where rounding decides the
+count anyway.
+*/
+
+use crate::fixed::{self, Fixed};
+use crate::{
+    bands, Cx, DrawParams, Grid, IterResult, Limits, OrbitState, Pixels,
+    COLOR_MAP_LENGTH,
+};
+
+/**
+How much smaller (squared) than the reference orbit a point's orbit can get
+before it's taken to have glitched.
+*/
+const GLITCH_TOLERANCE: f64 = 1e-6;
+
+/// The most new references `fix_glitches()` takes before it gives up.
+const MAX_REFERENCES: usize = 64;
+
+/**
+How much smaller than the series' first term its last has to be for it to
+do instead of iterating.
+*/
+const SERIES_TOLERANCE: f64 = 1e-12;
+
+/// A reference point and its orbit.
+pub struct Reference {
+    /// how far the reference point is from the corner of the image
+    pub offset: Cx,
+    /**
+    The orbit, starting with `z[0] = 0`, rounded to `f64`; `len` of them,
+    up to the first that diverged (or the iteration limit).
+    */
+    z: [Cx; COLOR_MAP_LENGTH],
+    len: usize,
+    /**
+    How many iterations every point can skip, by evaluating the series (see
+    the module docs) with coefficients `series`: `A`, and `B` and `C` times
+    `radius` (how far the furthest pixel is from the reference point) and
+    its square.
+    */
+    skip: usize,
+    series: [Cx; 3],
+    radius: f64,
+    /// The smallest `|z|²` the reference got to in the skipped iterations
+    skip_min_sqmod: f64,
+}
+
+impl Reference {
+    pub const fn new() -> Reference {
+        Reference {
+            offset: Cx { re: 0.0, im: 0.0 },
+            z: [Cx { re: 0.0, im: 0.0 }; COLOR_MAP_LENGTH],
+            len: 0,
+            skip: 0,
+            series: [Cx { re: 0.0, im: 0.0 }; 3],
+            radius: 0.0,
+            skip_min_sqmod: 0.0,
+        }
+    }
+
+    /**
+    Make this the orbit of the point `offset` from the corner of the image
+    `dp` describes, iterated with `limits`, and how many of its iterations
+    the series can stand in for.
+    */
+    pub fn compute(&mut self, dp: &DrawParams, offset: Cx, limits: &Limits) {
+        let len = fixed::limbs_for(dp.spacing());
+        let (c_re, c_im) = dp.point_fixed(&offset, len);
+        let (mut re, mut im) = (Fixed::zero(len), Fixed::zero(len));
+        self.offset = offset;
+        self.z[0] = Cx { re: 0.0, im: 0.0 };
+        self.len = 1;
+
+        let (xmax, ymax) = (dp.xpix.saturating_sub(1), dp.ypix.saturating_sub(1));
+        let corners = [(0, 0), (xmax, 0), (0, ymax), (xmax, ymax)];
+        let radius = corners.iter()
+            .map(|&(xp, yp)| norm(&dp.offset_at(xp, yp).sub(&offset)))
+            .fold(dp.spacing(), f64::max);
+        let zero = Cx { re: 0.0, im: 0.0 };
+        let (mut a, mut b, mut c) = (zero, zero, zero);
+        let mut approximating = true;
+        let mut min_sqmod = f64::INFINITY;
+        self.skip = 0;
+        self.series = [zero; 3];
+        self.radius = radius;
+        self.skip_min_sqmod = min_sqmod;
+
+        for n in 1..=limits.iter_limit as usize {
+            if approximating {
+                let twice_z = self.z[n - 1].add(&self.z[n - 1]);
+                let ar = Cx { re: a.re * radius, im: a.im * radius };
+                c = twice_z.mul(&c).add(&ar.mul(&b).add(&ar.mul(&b)));
+                b = twice_z.mul(&b).add(&ar.mul(&a));
+                a = twice_z.mul(&a).add(&Cx { re: 1.0, im: 0.0 });
+            }
+            let (sq_re, sq_im) = (re.sqr(), im.sqr());
+            im = re.mul(&im).twice().add(&c_im);
+            re = sq_re.sub(&sq_im).add(&c_re);
+            let z = Cx { re: re.to_f64(), im: im.to_f64() };
+            self.z[n] = z;
+            self.len = n + 1;
+            if z.sqmod() > limits.sq_mod_limit { break; }
+            if approximating && norm(&c) <= SERIES_TOLERANCE * norm(&a) {
+                self.skip = n;
+                self.series = [a, b, c];
+                self.skip_min_sqmod = min_sqmod;
+            } else {
+                approximating = false;
+            }
+            min_sqmod = min_sqmod.min(z.sqmod());
+        }
+    }
+}
+
+/// The current image's reference, and a spare for `fix_glitches()`.
+pub struct References { pub main: Reference, pub spare: Reference }
+
+impl References {
+    pub const fn new() -> References {
+        References { main: Reference::new(), spare: Reference::new() }
+    }
+}
+
+/// A point that glitched at iteration `n`, having got to `z`.
+fn glitched(n: u16, z: Cx) -> IterResult {
+    IterResult {
+        n, interior: 0, orbit: 0,
+        state: OrbitState { z, min_sqmod: f64::NAN },
+    }
+}
+
+fn is_glitched(state: &OrbitState) -> bool { state.min_sqmod.is_nan() }
+
+/// `|re| + |im|`, which (unlike `sqmod()`) doesn't underflow for tiny `z`s.
+fn norm(z: &Cx) -> f64 { z.re.abs() + z.im.abs() }
+
+/**
+`mandelbrot_iter()` for the point `dc` away from `r`'s reference point, or
+a glitched result (see the module docs) if `r` won't do for it.
+*/
+pub fn mandelbrot_iter(r: &Reference, dc: Cx, limits: &Limits) -> IterResult {
+    let u = Cx { re: dc.re / r.radius, im: dc.im / r.radius };
+    let [a, b, c] = r.series;
+    let mut dz = c.mul(&u).add(&b).mul(&u).add(&a).mul(&dc);
+    let mut cur = r.z[r.skip].add(&dz);
+    let mut min_sqmod = r.skip_min_sqmod;
+    if r.skip > 0 { min_sqmod = min_sqmod.min(cur.sqmod()); }
+
+    for n in r.skip as u16..limits.iter_limit {
+        let k = n as usize;
+        if k + 1 >= r.len { return glitched(n, cur); }
+        let big = r.z[k];
+        dz = big.add(&big).add(&dz).mul(&dz).add(&dc);
+        let big = r.z[k + 1];
+        cur = big.add(&dz);
+        let sqmod = cur.sqmod();
+        if sqmod < min_sqmod { min_sqmod = sqmod; }
+        if sqmod > limits.sq_mod_limit {
+            return IterResult::escaped(
+                n, &cur, min_sqmod, limits.sq_mod_limit,
+                core::f64::consts::LN_2, limits.axis
+            );
+        }
+        if sqmod < GLITCH_TOLERANCE * big.sqmod() { return glitched(n, cur); }
+    }
+    IterResult::captive(limits.iter_limit, limits.metric, min_sqmod, &cur)
+}
+
+/**
+Iterate the glitched pixels among those in `grid` again, in the image `dp`
+describes (whose data are in `px`), with `limits` and new references
+computed in `spare`, until none are left. Each new reference is the
+glitched pixel whose orbit got closest to 0, which is where its glitch is
+worst. After `MAX_REFERENCES` of them, whatever's still
+glitched is left with the count it glitched at.
+*/
+pub fn fix_glitches(
+    dp: &DrawParams,
+    spare: &mut Reference,
+    grid: Grid,
+    mut px: Pixels,
+    limits: &Limits,
+) {
+    let xpix = dp.xpix;
+    let npix = xpix * dp.ypix;
+    for _ in 0..MAX_REFERENCES {
+        let mut worst = None;
+        let mut worst_sqmod = f64::INFINITY;
+        for yp in 0..dp.ypix {
+            let (x0, dx) = match grid.columns(yp) {
+                Some(c) => c,
+                None => { continue; },
+            };
+            for xp in (x0..xpix).step_by(dx) {
+                let s = &px.state[yp * xpix + xp];
+                if is_glitched(s) && (worst.is_none() || s.z.sqmod() < worst_sqmod) {
+                    worst = Some((xp, yp));
+                    worst_sqmod = s.z.sqmod();
+                }
+            }
+        }
+        let (wx, wy) = match worst {
+            Some(p) => p,
+            None => { return; },
+        };
+        spare.compute(dp, dp.offset_at(wx, wy), limits);
+        let r: &Reference = spare;
+
+        bands::in_bands(xpix, dp.ypix, px.starting_at(0), |rows, mut px| {
+            for yp in rows.clone() {
+                let (x0, dx) = match grid.columns(yp) {
+                    Some(c) => c,
+                    None => { continue; },
+                };
+                for xp in (x0..xpix).step_by(dx) {
+                    let idx = (yp - rows.start) * xpix + xp;
+                    if !is_glitched(&px.state[idx]) { continue; }
+                    let res = mandelbrot_iter(r, dp.offset_at(xp, yp).sub(&r.offset), limits);
+                    px.set(idx, &res);
+                }
+            }
+        });
+    }
+    for s in px.state[..npix].iter_mut().filter(|s| is_glitched(s)) {
+        *s = OrbitState::start(Cx { re: 0.0, im: 0.0 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Grid, DEFAULT_DRAW_PARAMS};
+
+    /**
+    At a shallow zoom, where `f64` is plenty, iterating each pixel as its
+    difference from a reference gets the same counts as iterating it
+    directly, apart from the glitched pixels, and once those are fixed,
+    they do too.
+    */
+    #[test]
+    fn matches_double() {
+        const XPIX: usize = 160;
+        const YPIX: usize = 120;
+        let _guard = crate::tests::lock();
+        // Far too big for the stack, so they're the ones in `REFERENCES`.
+        let refs = unsafe { crate::references() };
+        let views = [(-0.7485, 0.1011, 0.003), (-2.0, 1.0, 3.0), (0.2501, 0.0012, 0.0008)];
+        let mut any_glitches = false;
+        for (x, y, width) in views {
+            let dp = DrawParams {
+                xpix: XPIX, ypix: YPIX, full_xpix: XPIX, full_ypix: YPIX,
+                x, y, width,
+                ..DEFAULT_DRAW_PARAMS
+            };
+            let limits = dp.limits(400);
+            let r = &mut refs.main;
+            r.compute(&dp, dp.offset_at(XPIX / 2, YPIX / 2), &limits);
+            let mut itermap = vec![0; XPIX * YPIX];
+            let mut interior = vec![0; XPIX * YPIX];
+            let mut orbit = vec![0; XPIX * YPIX];
+            let mut state = vec![OrbitState::start(Cx { re: 0.0, im: 0.0 }); XPIX * YPIX];
+            let mut px = Pixels {
+                itermap: &mut itermap, interior: &mut interior,
+                orbit: &mut orbit, state: &mut state,
+            };
+            let mut want = vec![0; XPIX * YPIX];
+            for yp in 0..YPIX {
+                for xp in 0..XPIX {
+                    let idx = yp * XPIX + xp;
+                    let c = dp.point_at(xp, yp);
+                    want[idx] = crate::mandelbrot_iter(c.re, c.im, &limits).n;
+                    let res = mandelbrot_iter(r, dp.offset_at(xp, yp).sub(&r.offset), &limits);
+                    px.set(idx, &res);
+                    if is_glitched(&res.state) {
+                        any_glitches = true;
+                    } else {
+                        assert_eq!(res.n, want[idx], "({}, {}) in {}", xp, yp, x);
+                    }
+                }
+            }
+            fix_glitches(&dp, &mut refs.spare, Grid::ALL, px.starting_at(0), &limits);
+            assert!(state.iter().all(|s| !is_glitched(s)));
+            assert!(itermap == want, "fixed glitches in {}", x);
+        }
+        assert!(any_glitches);
+    }
+}
@@ -0,0 +1,404 @@
+/*!
+Reusing the last image's iteration data for the next one.
+
+When the new view is the old one slid over by a whole number of pixels (as
+when clicking to recenter), most of the new image's pixels land exactly on
+old ones. The same goes, for every other pixel, when it's zoomed in by a
+factor of two with its corner on a half-pixel of the old one, and for the
+middle of the image when it's zoomed out by two with its corner on a whole
+pixel of the old one. Those pixels' data get moved over to where they
+belong in the new image, and only the rest need to be iterated: about three
+quarters of the pixels for a zoom either way, and just the strips along the
+edges for a pan.
+
+"Exactly" is up to rounding: the new corner is only ever within a few ulps
+of where it would be for a whole number of pixels, so the points the kept
+pixels stand for can be that far off from what a fresh render would use.
+Offsets that miss by more than `SLACK` pixels don't count.
+*/
+
+use core::ops::Range;
+
+use crate::{DrawParams, Grid, IteratorType, OrbitState, PixelIter, Pixels};
+
+/// How far from lining up an offset can be (in pixels) and still count.
+pub const SLACK: f64 = 1e-6;
+
+/// How big the new image's pixels are next to the old one's.
+#[derive(Clone, Copy)]
+enum Scale {
+    /// the same size (a pan)
+    Same,
+    /// half the size (zoomed in by two)
+    Half,
+    /// twice the size (zoomed out by two)
+    Double,
+}
+
+/**
+How the new image's pixels line up with the old one's along one axis.
+Along with the `scale`, `off` is where the new pixel 0 is in old pixels
+(counting in new pixels instead, for `Half`).
+*/
+#[derive(Clone, Copy)]
+struct Axis { scale: Scale, off: isize }
+
+impl Axis {
+    /// The old pixel new pixel `p` lands on, if any (whether or not it's in
+    /// the image).
+    fn source(&self, p: usize) -> Option<isize> {
+        let p = p as isize;
+        match self.scale {
+            Scale::Same => Some(p + self.off),
+            Scale::Double => Some(2 * p + self.off),
+            Scale::Half => {
+                let q = p + self.off;
+                if q % 2 == 0 { Some(q / 2) } else { None }
+            },
+        }
+    }
+
+    /**
+    The pixel (between two pixels, for `Half`) where new and old positions
+    are the same, and whether data have to be moved in order going `out`
+    from it or in toward it, so none of them get written over before they've
+    been moved. A pan has no such pixel, so it's off one end or the other.
+    */
+    fn pivot(&self) -> (isize, bool) {
+        match self.scale {
+            Scale::Same if self.off > 0 => (isize::MIN, true),
+            Scale::Same => (isize::MAX, true),
+            Scale::Double => (-self.off, true),
+            Scale::Half => (self.off, false),
+        }
+    }
+
+    /// Which of the `pix` new pixels land on old ones.
+    fn kept(&self, pix: usize) -> Span {
+        let lands = |p: usize| match self.source(p) {
+            Some(q) => q >= 0 && q < pix as isize,
+            None => false,
+        };
+        let step = match self.scale { Scale::Half => 2, _ => 1 };
+        match (0..pix).find(|&p| lands(p)) {
+            Some(first) => {
+                let mut end = first;
+                while end < pix && lands(end) { end += step; }
+                Span { first, end, step }
+            },
+            None => Span { first: 0, end: 0, step },
+        }
+    }
+}
+
+/// Every `step`th pixel from `first` up to (not including) `end`.
+#[derive(Clone, Copy)]
+struct Span { first: usize, end: usize, step: usize }
+
+impl Span {
+    fn has(&self, p: usize) -> bool {
+        p >= self.first && p < self.end && (p - self.first).is_multiple_of(self.step)
+    }
+
+    fn count(&self) -> usize {
+        if self.end <= self.first { 0 }
+        else { (self.end - self.first).div_ceil(self.step) }
+    }
+
+    fn at(&self, i: usize) -> usize { self.first + i * self.step }
+
+    /// Call `f` with each pixel, going `out` from `pivot` or in toward it.
+    fn visit<F: FnMut(usize)>(&self, (pivot, out): (isize, bool), mut f: F) {
+        let n = self.count();
+        let below = (0..n).filter(|&i| (self.at(i) as isize) < pivot).count();
+        if out {
+            for i in below..n { f(self.at(i)); }
+            for i in (0..below).rev() { f(self.at(i)); }
+        } else {
+            for i in (below..n).rev() { f(self.at(i)); }
+            for i in 0..below { f(self.at(i)); }
+        }
+    }
+}
+
+/**
+The pixels of the new image whose data came from the old one (or from
+somewhere else besides iterating them; see the `symmetry` module).
+*/
+#[derive(Clone, Copy)]
+pub struct Kept { x: Span, y: Span }
+
+impl Kept {
+    /// Columns `x0..x1` of rows `y0..y1`.
+    pub fn rect(x0: usize, x1: usize, y0: usize, y1: usize) -> Kept {
+        Kept {
+            x: Span { first: x0, end: x1, step: 1 },
+            y: Span { first: y0, end: y1, step: 1 },
+        }
+    }
+
+    pub fn has(&self, xp: usize, yp: usize) -> bool {
+        self.x.has(xp) && self.y.has(yp)
+    }
+
+    pub fn count(&self) -> usize { self.x.count() * self.y.count() }
+
+    /// How many pixels of row `yp` still have to be iterated.
+    pub fn todo_in_row(&self, xpix: usize, yp: usize) -> usize {
+        if self.y.has(yp) { xpix - self.x.count() } else { xpix }
+    }
+}
+
+/**
+Whether the old image `old` can lend its data to the new one `new` at all:
+it has to be finished, it has to be a whole image (no tile, no apron) of the
+same size, and it has to have been iterated exactly the same way, with the
+same iteration limit (`map_length`).
+*/
+fn compatible(old: &DrawParams, new: &DrawParams, map_length: usize) -> bool {
+    let whole = |dp: &DrawParams| {
+        dp.full_xpix == dp.xpix && dp.full_ypix == dp.ypix
+            && dp.x_off == 0 && dp.y_off == 0
+            && dp.apron_x == 0 && dp.apron_y == 0
+    };
+    let same_iterator = match (old.iterator, new.iterator) {
+        (IteratorType::Mandelbrot, IteratorType::Mandelbrot) => true,
+        (IteratorType::Polynomial, IteratorType::Polynomial) =>
+            old.n_coeffs == new.n_coeffs && old.coeffs == new.coeffs,
+        _ => false,
+    };
+    old.block == 1 && whole(old) && whole(new)
+        && old.xpix == new.xpix && old.ypix == new.ypix
+        && old.matrix == new.matrix
+        && old.warp == new.warp
+        && same_iterator
+        && old.colormap_length == map_length
+        && old.interior_metric == new.interior_metric
+        && old.texture_axis == new.texture_axis
+        && old.precision() == new.precision()
+}
+
+/**
+How the pixels of the new view line up with the old one's, if they do:
+the new view has to be the same width as the old, or twice or half it,
+with its corner (in old pixels) on a whole pixel, or a half pixel when it's
+zoomed in.
+*/
+fn alignment(old: &DrawParams, new: &DrawParams) -> Option<(Axis, Axis)> {
+    let scale = if new.width == old.width { Scale::Same }
+        else if new.width * 2.0 == old.width { Scale::Half }
+        else if new.width == old.width * 2.0 { Scale::Double }
+        else { return None; };
+
+    let xpixf = old.full_xpix as f64;
+    let ypixf = old.full_ypix as f64;
+    let height = old.width * ypixf / xpixf;
+    let (pw, ph) = (old.width / xpixf, height / ypixf);
+
+    // The corner moved by `matrix` times (u, v); undo the matrix.
+    let dre = (new.x - old.x) + (new.x_lo - old.x_lo) + new.x_rest.sub(&old.x_rest).to_f64();
+    let dim = (new.y - old.y) + (new.y_lo - old.y_lo) + new.y_rest.sub(&old.y_rest).to_f64();
+    let [a, b, c, d] = new.matrix;
+    let det = a * d - b * c;
+    if det == 0.0 { return None; }
+    let u = (d * dre - b * dim) / det;
+    let v = (a * dim - c * dre) / det;
+
+    let axis = |k: f64| {
+        let k = match scale { Scale::Half => 2.0 * k, _ => k };
+        let r = crate::fmath::floor(k + 0.5);
+        if (k - r).abs() > SLACK || r.abs() > 4.0 * (old.xpix + old.ypix) as f64 {
+            None
+        } else {
+            Some(Axis { scale, off: r as isize })
+        }
+    };
+    Some((axis(u / pw)?, axis(-v / ph)?))
+}
+
+/**
+Move the data in `buf` (an `xpix`-wide image) from the old pixels to the
+`kept` new ones they land on. Pixels with nothing to move into them are left
+as they were.
+*/
+fn shift<T: Copy>(buf: &mut [T], xpix: usize, kept: &Kept, (ax, ay): (Axis, Axis)) {
+    kept.y.visit(ay.pivot(), |yp| {
+        let sy = ay.source(yp).unwrap_or(0) as usize;
+        kept.x.visit(ax.pivot(), |xp| {
+            let sx = ax.source(xp).unwrap_or(0) as usize;
+            buf[yp * xpix + xp] = buf[sy * xpix + sx];
+        });
+    });
+}
+
+/**
+If `new` can reuse the data `old` left in `buff`, `interior`, `orbit`, and
+`state`, move them into place and return which pixels they cover.
+*/
+pub fn reuse(
+    old: &DrawParams,
+    new: &DrawParams,
+    map_length: usize,
+    buff: &mut [u16],
+    interior: &mut [u16],
+    orbit: &mut [u32],
+    state: &mut [OrbitState],
+) -> Option<Kept> {
+    if !compatible(old, new, map_length) { return None; }
+    let axes = alignment(old, new)?;
+    let (xpix, ypix) = (new.xpix, new.ypix);
+    let kept = Kept { x: axes.0.kept(xpix), y: axes.1.kept(ypix) };
+    if kept.x.count() == 0 || kept.y.count() == 0 { return None; }
+    shift(buff, xpix, &kept, axes);
+    shift(interior, xpix, &kept, axes);
+    shift(orbit, xpix, &kept, axes);
+    shift(state, xpix, &kept, axes);
+    Some(kept)
+}
+
+/**
+Iterate the pixels of `rows` in `grid` that aren't `kept`, into `px`
+(which starts at the first of those rows), four at a time.
+*/
+pub fn fill_rows(
+    it: &PixelIter,
+    kept: &Kept,
+    grid: Grid,
+    rows: Range<usize>,
+    mut px: Pixels,
+) {
+    let xpix = it.dp.xpix;
+    let top = rows.start;
+    let mut waiting = [(0, 0); 4];
+    let mut n = 0;
+    let mut flush = |waiting: &mut [(usize, usize); 4], n: usize| {
+        for i in n..4 { waiting[i] = waiting[0]; }
+        let res = it.at4(*waiting);
+        for i in 0..n { px.set((waiting[i].1 - top) * xpix + waiting[i].0, &res[i]); }
+    };
+    for yp in rows {
+        let (x0, dx) = match grid.columns(yp) {
+            Some(c) => c,
+            None => { continue; },
+        };
+        for xp in (x0..xpix).step_by(dx) {
+            if kept.has(xp, yp) { continue; }
+            waiting[n] = (xp, yp);
+            n += 1;
+            if n == 4 {
+                flush(&mut waiting, n);
+                n = 0;
+            }
+        }
+    }
+    if n > 0 { flush(&mut waiting, n); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cx, DEFAULT_DRAW_PARAMS};
+
+    const XPIX: usize = 16;
+    const YPIX: usize = 12;
+
+    /// A whole `XPIX` x `YPIX` image, `width` wide, with its corner at `x` + i`y`.
+    fn view(x: f64, y: f64, width: f64) -> DrawParams {
+        DrawParams {
+            xpix: XPIX, ypix: YPIX, full_xpix: XPIX, full_ypix: YPIX,
+            x, y, width, block: 1,
+            ..DEFAULT_DRAW_PARAMS
+        }
+    }
+
+    /**
+    Move an image of the old view's points over to the new view, which is
+    `scale` times as wide with its corner `(dx, dy)` old pixels over and down,
+    and check every new pixel's point got there that should have. The
+    pixels are powers of two on the plane, so all the points are exact.
+    */
+    fn check_shift(scale: f64, (dx, dy): (f64, f64)) {
+        let old = view(-2.0, 1.5, 4.0);
+        let pix = old.width / XPIX as f64;
+        let new = view(old.x + dx * pix, old.y - dy * pix, old.width * scale);
+        let axes = alignment(&old, &new).unwrap();
+        let kept = Kept { x: axes.0.kept(XPIX), y: axes.1.kept(YPIX) };
+
+        let mut buf = [Cx { re: 0.0, im: 0.0 }; XPIX * YPIX];
+        for yp in 0..YPIX {
+            for xp in 0..XPIX { buf[yp * XPIX + xp] = old.point_at(xp, yp); }
+        }
+        shift(&mut buf, XPIX, &kept, axes);
+
+        let mut n_kept = 0;
+        for yp in 0..YPIX {
+            for xp in 0..XPIX {
+                let p = new.point_at(xp, yp);
+                let lands = (0..YPIX).any(|j| (0..XPIX).any(|i| old.point_at(i, j) == p));
+                assert!(kept.has(xp, yp) == lands, "({}, {}) kept {}", xp, yp, lands);
+                if lands {
+                    assert!(buf[yp * XPIX + xp] == p, "({}, {}) moved wrong", xp, yp);
+                    n_kept += 1;
+                }
+            }
+        }
+        assert_eq!(kept.count(), n_kept);
+        assert!(n_kept > 0);
+    }
+
+    #[test]
+    fn pans() {
+        for off in [(3.0, 2.0), (-3.0, -2.0), (5.0, -1.0), (-7.0, 4.0), (0.0, 0.0)] {
+            check_shift(1.0, off);
+        }
+    }
+
+    #[test]
+    fn zooms_in() {
+        for off in [(4.0, 3.0), (-2.0, -1.0), (1.5, 2.5), (-0.5, 0.5), (6.5, -3.0)] {
+            check_shift(0.5, off);
+        }
+    }
+
+    #[test]
+    fn zooms_out() {
+        for off in [(-8.0, -6.0), (3.0, 2.0), (-1.0, 5.0), (7.0, -4.0)] {
+            check_shift(2.0, off);
+        }
+    }
+
+    #[test]
+    fn misses() {
+        let old = view(-2.0, 1.5, 4.0);
+        let pix = old.width / XPIX as f64;
+        for (x, width) in [(0.3, 1.0), (0.25, 0.5), (0.5, 2.0), (1.0, 3.0)] {
+            let new = view(old.x + x * pix, old.y, old.width * width);
+            assert!(alignment(&old, &new).is_none(), "{} {}", x, width);
+        }
+    }
+
+    #[test]
+    fn visits_each_once() {
+        let span = Span { first: 1, end: 12, step: 2 };
+        for pivot in [isize::MIN, -3, 0, 1, 4, 5, 11, 20, isize::MAX] {
+            for out in [true, false] {
+                let mut seen = [0; 12];
+                let mut last = None;
+                span.visit((pivot, out), |p| {
+                    seen[p] += 1;
+                    // Going out, each pixel is farther from the pivot than
+                    // the one before on its side; going in, nearer.
+                    if let Some(q) = last {
+                        let (p, q) = (p as isize, q as isize);
+                        if (p < pivot) == (q < pivot) {
+                            let farther = p.abs_diff(pivot) > q.abs_diff(pivot);
+                            assert_eq!(farther, out, "pivot {} p {} q {}", pivot, p, q);
+                        }
+                    }
+                    last = Some(p);
+                });
+                for (p, &n) in seen.iter().enumerate() { assert_eq!(n, span.has(p) as i32); }
+            }
+        }
+    }
+}
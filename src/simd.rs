@@ -0,0 +1,292 @@
+/*!
+Iterating two points at once, one per lane of a 128-bit SIMD vector.
+
+`F64x2` is a pair of `f64`s with just the handful of operations the
+iterators need. It's wasm `simd128` when the module is built with that
+feature (see `build.sh`), SSE2 on x86_64, and a plain array anywhere else.
+Every lane does exactly the same arithmetic, in the same order, as the
+scalar iterators in `lib.rs`, so images come out bit-for-bit the same
+whichever gets used.
+
+A lane keeps getting iterated after its point diverges (the other one might
+not have yet); its result is recorded the moment it does, and whatever
+happens to it afterward is ignored.
+*/
+
+use crate::{Cx, IterResult, Limits, Poly, MAX_COEFFS};
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod lanes {
+    use core::arch::wasm32::*;
+
+    #[derive(Clone, Copy)]
+    pub struct F64x2(v128);
+
+    /// Lane mask, as returned by comparisons.
+    #[derive(Clone, Copy)]
+    pub struct Mask(v128);
+
+    impl F64x2 {
+        pub fn new(a: f64, b: f64) -> F64x2 { F64x2(f64x2(a, b)) }
+        pub fn splat(a: f64) -> F64x2 { F64x2(f64x2_splat(a)) }
+        pub fn add(self, o: F64x2) -> F64x2 { F64x2(f64x2_add(self.0, o.0)) }
+        pub fn sub(self, o: F64x2) -> F64x2 { F64x2(f64x2_sub(self.0, o.0)) }
+        pub fn mul(self, o: F64x2) -> F64x2 { F64x2(f64x2_mul(self.0, o.0)) }
+        pub fn lt(self, o: F64x2) -> Mask { Mask(f64x2_lt(self.0, o.0)) }
+        pub fn gt(self, o: F64x2) -> Mask { Mask(f64x2_gt(self.0, o.0)) }
+        /// `t` in the lanes where `m` is set, `self` elsewhere
+        pub fn select(self, m: Mask, t: F64x2) -> F64x2 {
+            F64x2(v128_bitselect(t.0, self.0, m.0))
+        }
+        pub fn lane(self, i: usize) -> f64 {
+            if i == 0 { f64x2_extract_lane::<0>(self.0) }
+            else { f64x2_extract_lane::<1>(self.0) }
+        }
+    }
+
+    impl Mask {
+        /// bit 0 for lane 0, bit 1 for lane 1
+        pub fn bits(self) -> u8 { i64x2_bitmask(self.0) }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod lanes {
+    use core::arch::x86_64::*;
+
+    #[derive(Clone, Copy)]
+    pub struct F64x2(__m128d);
+
+    /// Lane mask, as returned by comparisons.
+    #[derive(Clone, Copy)]
+    pub struct Mask(__m128d);
+
+    // SSE2 is part of the x86_64 baseline, so none of these can fail.
+    impl F64x2 {
+        pub fn new(a: f64, b: f64) -> F64x2 { unsafe { F64x2(_mm_set_pd(b, a)) } }
+        pub fn splat(a: f64) -> F64x2 { unsafe { F64x2(_mm_set1_pd(a)) } }
+        pub fn add(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_add_pd(self.0, o.0)) } }
+        pub fn sub(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_sub_pd(self.0, o.0)) } }
+        pub fn mul(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_mul_pd(self.0, o.0)) } }
+        pub fn lt(self, o: F64x2) -> Mask { unsafe { Mask(_mm_cmplt_pd(self.0, o.0)) } }
+        pub fn gt(self, o: F64x2) -> Mask { unsafe { Mask(_mm_cmpgt_pd(self.0, o.0)) } }
+        /// `t` in the lanes where `m` is set, `self` elsewhere
+        pub fn select(self, m: Mask, t: F64x2) -> F64x2 {
+            unsafe {
+                F64x2(_mm_or_pd(_mm_and_pd(m.0, t.0), _mm_andnot_pd(m.0, self.0)))
+            }
+        }
+        pub fn lane(self, i: usize) -> f64 {
+            let mut out = [0.0f64; 2];
+            unsafe { _mm_storeu_pd(out.as_mut_ptr(), self.0); }
+            out[i]
+        }
+    }
+
+    impl Mask {
+        /// bit 0 for lane 0, bit 1 for lane 1
+        pub fn bits(self) -> u8 { unsafe { _mm_movemask_pd(self.0) as u8 } }
+    }
+}
+
+#[cfg(not(any(
+    all(target_arch = "wasm32", target_feature = "simd128"),
+    target_arch = "x86_64"
+)))]
+mod lanes {
+    /// No SIMD here; the compiler can make of this what it will.
+    #[derive(Clone, Copy)]
+    pub struct F64x2([f64; 2]);
+
+    /// Lane mask, as returned by comparisons.
+    #[derive(Clone, Copy)]
+    pub struct Mask([bool; 2]);
+
+    impl F64x2 {
+        pub fn new(a: f64, b: f64) -> F64x2 { F64x2([a, b]) }
+        pub fn splat(a: f64) -> F64x2 { F64x2([a, a]) }
+        pub fn add(self, o: F64x2) -> F64x2 { F64x2([self.0[0] + o.0[0], self.0[1] + o.0[1]]) }
+        pub fn sub(self, o: F64x2) -> F64x2 { F64x2([self.0[0] - o.0[0], self.0[1] - o.0[1]]) }
+        pub fn mul(self, o: F64x2) -> F64x2 { F64x2([self.0[0] * o.0[0], self.0[1] * o.0[1]]) }
+        pub fn lt(self, o: F64x2) -> Mask { Mask([self.0[0] < o.0[0], self.0[1] < o.0[1]]) }
+        pub fn gt(self, o: F64x2) -> Mask { Mask([self.0[0] > o.0[0], self.0[1] > o.0[1]]) }
+        /// `t` in the lanes where `m` is set, `self` elsewhere
+        pub fn select(self, m: Mask, t: F64x2) -> F64x2 {
+            F64x2([
+                if m.0[0] { t.0[0] } else { self.0[0] },
+                if m.0[1] { t.0[1] } else { self.0[1] },
+            ])
+        }
+        pub fn lane(self, i: usize) -> f64 { self.0[i] }
+    }
+
+    impl Mask {
+        /// bit 0 for lane 0, bit 1 for lane 1
+        pub fn bits(self) -> u8 { (self.0[0] as u8) | ((self.0[1] as u8) << 1) }
+    }
+}
+
+use lanes::F64x2;
+
+/// Two complex numbers, one per lane.
+#[derive(Clone, Copy)]
+struct Cx2 { re: F64x2, im: F64x2 }
+
+impl Cx2 {
+    fn new(a: &Cx, b: &Cx) -> Cx2 {
+        Cx2 { re: F64x2::new(a.re, b.re), im: F64x2::new(a.im, b.im) }
+    }
+
+    fn splat(a: &Cx) -> Cx2 {
+        Cx2 { re: F64x2::splat(a.re), im: F64x2::splat(a.im) }
+    }
+
+    fn add(&self, o: &Cx2) -> Cx2 {
+        Cx2 { re: self.re.add(o.re), im: self.im.add(o.im) }
+    }
+
+    // Same operations in the same order as `Cx::mul()`.
+    fn mul(&self, o: &Cx2) -> Cx2 {
+        Cx2 {
+            re: self.re.mul(o.re).sub(self.im.mul(o.im)),
+            im: self.im.mul(o.re).add(self.re.mul(o.im)),
+        }
+    }
+
+    fn sqmod(&self) -> F64x2 { self.re.mul(self.re).add(self.im.mul(self.im)) }
+
+    fn lane(&self, i: usize) -> Cx { Cx { re: self.re.lane(i), im: self.im.lane(i) } }
+}
+
+/**
+Everything both iterators do after each step: track the smallest squared
+modulus, and record the results of lanes that have just diverged. Returns
+the bits of the lanes that are done.
+*/
+fn step_done(
+    n: u16,
+    cur: &Cx2,
+    min_sqmod: &mut F64x2,
+    done: u8,
+    res: &mut [IterResult; 2],
+    limits: &Limits,
+    ln_degree: f64,
+) -> u8 {
+    let sqmod = cur.sqmod();
+    *min_sqmod = min_sqmod.select(sqmod.lt(*min_sqmod), sqmod);
+    let escaped = sqmod.gt(F64x2::splat(limits.sq_mod_limit)).bits() & !done;
+    for (i, r) in res.iter_mut().enumerate() {
+        if escaped & (1 << i) != 0 {
+            *r = IterResult::escaped(
+                n, &cur.lane(i), min_sqmod.lane(i), limits.sq_mod_limit,
+                ln_degree, limits.axis
+            );
+        }
+    }
+    done | escaped
+}
+
+/// The results for the lanes that never diverged.
+fn finish(
+    done: u8,
+    cur: &Cx2,
+    min_sqmod: F64x2,
+    res: &mut [IterResult; 2],
+    limits: &Limits,
+) {
+    for (i, r) in res.iter_mut().enumerate() {
+        if done & (1 << i) == 0 {
+            *r = IterResult::captive(
+                limits.iter_limit, limits.metric, min_sqmod.lane(i), &cur.lane(i)
+            );
+        }
+    }
+}
+
+/// `mandelbrot_iter()` for the two points `a` and `b` at once.
+pub fn mandelbrot_iter2(a: &Cx, b: &Cx, limits: &Limits) -> [IterResult; 2] {
+    let c = Cx2::new(a, b);
+    let mut cur = Cx2::splat(&Cx { re: 0.0, im: 0.0 });
+    let mut min_sqmod = F64x2::splat(f64::INFINITY);
+    let mut res = [IterResult::escaped_plain(0); 2];
+    let mut done = 0u8;
+
+    for n in 0..limits.iter_limit {
+        cur = c.add(&cur.mul(&cur));
+        done = step_done(
+            n, &cur, &mut min_sqmod, done, &mut res,
+            limits, core::f64::consts::LN_2
+        );
+        if done == 0b11 { return res; }
+    }
+    finish(done, &cur, min_sqmod, &mut res, limits);
+    res
+}
+
+/// `polynomial_iter()` for the two points `a` and `b` at once.
+pub fn polynomial_iter2(a: &Cx, b: &Cx, poly: &Poly, limits: &Limits) -> [IterResult; 2] {
+    let mut cx2 = [Cx2::splat(&Cx { re: 0.0, im: 0.0 }); MAX_COEFFS];
+    for (v, c) in cx2.iter_mut().zip(poly.coeffs.iter()) { *v = Cx2::splat(c); }
+    let degree = poly.degree;
+    let mut cur = Cx2::new(a, b);
+    let mut min_sqmod = F64x2::splat(f64::INFINITY);
+    let mut res = [IterResult::escaped_plain(0); 2];
+    let mut done = 0u8;
+
+    for n in 0..limits.iter_limit {
+        let mut new = Cx2::splat(&Cx { re: 0.0, im: 0.0 });
+        let mut z   = Cx2::splat(&Cx { re: 1.0, im: 0.0 });
+        for c in &cx2[..degree] {
+            let t = z.mul(c);
+            new = new.add(&t);
+            z = z.mul(&cur);
+        }
+        let t = z.mul(&cx2[degree]);
+        cur = new.add(&t);
+        done = step_done(
+            n, &cur, &mut min_sqmod, done, &mut res,
+            limits, poly.ln_degree
+        );
+        if done == 0b11 { return res; }
+    }
+    finish(done, &cur, min_sqmod, &mut res, limits);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::same_result;
+    use crate::{mandelbrot_iter, polynomial_iter, InteriorMetric, TextureAxis};
+
+    /// Each lane comes out exactly as the scalar iterators would.
+    #[test]
+    fn two_match_one() {
+        let mut coeffs = [Cx { re: 0.0, im: 0.0 }; MAX_COEFFS];
+        coeffs[0] = Cx { re: -0.1, im: 0.65 };
+        coeffs[2] = Cx { re: 1.0, im: 0.0 };
+        let poly = Poly::new(&coeffs, 3).unwrap();
+        // Escaping at different times, or not at all, in either lane.
+        let pairs = [
+            (Cx { re: -0.75, im: 0.1 }, Cx { re: 0.3, im: 0.5 }),
+            (Cx { re: 0.3, im: 0.5 }, Cx { re: -0.75, im: 0.1 }),
+            (Cx { re: -0.1, im: 0.2 }, Cx { re: 0.26, im: -0.003 }),
+            (Cx { re: 1.5, im: 1.5 }, Cx { re: -0.1, im: 0.2 }),
+        ];
+        for axis in [TextureAxis::Off, TextureAxis::Angle, TextureAxis::Trap] {
+            for metric in [InteriorMetric::MinModulus, InteriorMetric::FinalModulus] {
+                let limits = Limits { sq_mod_limit: 1_000_000.0, iter_limit: 300, metric, axis };
+                for (a, b) in pairs {
+                    let two = mandelbrot_iter2(&a, &b, &limits);
+                    for (c, res) in [a, b].iter().zip(two.iter()) {
+                        assert!(same_result(&mandelbrot_iter(c.re, c.im, &limits), res));
+                    }
+                    let two = polynomial_iter2(&a, &b, &poly, &limits);
+                    for (c, res) in [a, b].iter().zip(two.iter()) {
+                        assert!(same_result(&polynomial_iter(c.re, c.im, &poly, &limits), res));
+                    }
+                }
+            }
+        }
+    }
+}
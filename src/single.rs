@@ -0,0 +1,395 @@
+/*!
+Iterating in single precision, four points at once (or one, when that's
+all there is).
+
+At shallow zooms `f64` is far more precision than the image can show, and
+`f32` does just as well: a 128-bit SIMD vector holds four of them instead
+of two `f64`s, so this goes about twice as fast. `Precision` in `lib.rs`
+says when it gets used.
+
+`F32x4` is four `f32`s with the same handful of operations as the `F64x2`
+in the `simd` module, and likewise wasm `simd128`, SSE on x86_64, or a
+plain array anywhere else. The arithmetic in each lane follows the `f64`
+iterators step for step, just rounded to `f32` along the way. Results are
+handed back as `f64`s, so everything downstream (smoothing, the orbit
+states for resuming) works the same; `f32` values survive the round trip
+exactly, so an orbit picked up again in single precision carries on as if
+it had never stopped.
+
+As with two lanes, a lane keeps getting iterated after its point diverges,
+and whatever happens to it afterward is ignored.
+
+For a lone point, `mandelbrot_iter()` and `polynomial_iter()` do the same
+arithmetic in plain `f32`s, so they come out exactly the same as any lane.
+*/
+
+use crate::{Cx, IterResult, Limits, OrbitState, Poly, MAX_COEFFS};
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod lanes {
+    use core::arch::wasm32::*;
+
+    #[derive(Clone, Copy)]
+    pub struct F32x4(v128);
+
+    /// Lane mask, as returned by comparisons.
+    #[derive(Clone, Copy)]
+    pub struct Mask(v128);
+
+    impl F32x4 {
+        pub fn new(v: [f32; 4]) -> F32x4 { F32x4(f32x4(v[0], v[1], v[2], v[3])) }
+        pub fn splat(a: f32) -> F32x4 { F32x4(f32x4_splat(a)) }
+        pub fn add(self, o: F32x4) -> F32x4 { F32x4(f32x4_add(self.0, o.0)) }
+        pub fn sub(self, o: F32x4) -> F32x4 { F32x4(f32x4_sub(self.0, o.0)) }
+        pub fn mul(self, o: F32x4) -> F32x4 { F32x4(f32x4_mul(self.0, o.0)) }
+        pub fn lt(self, o: F32x4) -> Mask { Mask(f32x4_lt(self.0, o.0)) }
+        pub fn gt(self, o: F32x4) -> Mask { Mask(f32x4_gt(self.0, o.0)) }
+        /// `t` in the lanes where `m` is set, `self` elsewhere
+        pub fn select(self, m: Mask, t: F32x4) -> F32x4 {
+            F32x4(v128_bitselect(t.0, self.0, m.0))
+        }
+        pub fn lanes(self) -> [f32; 4] {
+            [
+                f32x4_extract_lane::<0>(self.0), f32x4_extract_lane::<1>(self.0),
+                f32x4_extract_lane::<2>(self.0), f32x4_extract_lane::<3>(self.0),
+            ]
+        }
+    }
+
+    impl Mask {
+        /// bit `i` for lane `i`
+        pub fn bits(self) -> u8 { i32x4_bitmask(self.0) }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod lanes {
+    use core::arch::x86_64::*;
+
+    #[derive(Clone, Copy)]
+    pub struct F32x4(__m128);
+
+    /// Lane mask, as returned by comparisons.
+    #[derive(Clone, Copy)]
+    pub struct Mask(__m128);
+
+    // SSE is part of the x86_64 baseline, so none of these can fail.
+    impl F32x4 {
+        pub fn new(v: [f32; 4]) -> F32x4 { unsafe { F32x4(_mm_loadu_ps(v.as_ptr())) } }
+        pub fn splat(a: f32) -> F32x4 { unsafe { F32x4(_mm_set1_ps(a)) } }
+        pub fn add(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_add_ps(self.0, o.0)) } }
+        pub fn sub(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_sub_ps(self.0, o.0)) } }
+        pub fn mul(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_mul_ps(self.0, o.0)) } }
+        pub fn lt(self, o: F32x4) -> Mask { unsafe { Mask(_mm_cmplt_ps(self.0, o.0)) } }
+        pub fn gt(self, o: F32x4) -> Mask { unsafe { Mask(_mm_cmpgt_ps(self.0, o.0)) } }
+        /// `t` in the lanes where `m` is set, `self` elsewhere
+        pub fn select(self, m: Mask, t: F32x4) -> F32x4 {
+            unsafe {
+                F32x4(_mm_or_ps(_mm_and_ps(m.0, t.0), _mm_andnot_ps(m.0, self.0)))
+            }
+        }
+        pub fn lanes(self) -> [f32; 4] {
+            let mut out = [0.0f32; 4];
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), self.0); }
+            out
+        }
+    }
+
+    impl Mask {
+        /// bit `i` for lane `i`
+        pub fn bits(self) -> u8 { unsafe { _mm_movemask_ps(self.0) as u8 } }
+    }
+}
+
+#[cfg(not(any(
+    all(target_arch = "wasm32", target_feature = "simd128"),
+    target_arch = "x86_64"
+)))]
+mod lanes {
+    /// No SIMD here; the compiler can make of this what it will.
+    #[derive(Clone, Copy)]
+    pub struct F32x4([f32; 4]);
+
+    /// Lane mask, as returned by comparisons.
+    #[derive(Clone, Copy)]
+    pub struct Mask([bool; 4]);
+
+    impl F32x4 {
+        pub fn new(v: [f32; 4]) -> F32x4 { F32x4(v) }
+        pub fn splat(a: f32) -> F32x4 { F32x4([a; 4]) }
+        pub fn add(self, o: F32x4) -> F32x4 { F32x4(core::array::from_fn(|i| self.0[i] + o.0[i])) }
+        pub fn sub(self, o: F32x4) -> F32x4 { F32x4(core::array::from_fn(|i| self.0[i] - o.0[i])) }
+        pub fn mul(self, o: F32x4) -> F32x4 { F32x4(core::array::from_fn(|i| self.0[i] * o.0[i])) }
+        pub fn lt(self, o: F32x4) -> Mask { Mask(core::array::from_fn(|i| self.0[i] < o.0[i])) }
+        pub fn gt(self, o: F32x4) -> Mask { Mask(core::array::from_fn(|i| self.0[i] > o.0[i])) }
+        /// `t` in the lanes where `m` is set, `self` elsewhere
+        pub fn select(self, m: Mask, t: F32x4) -> F32x4 {
+            F32x4(core::array::from_fn(|i| if m.0[i] { t.0[i] } else { self.0[i] }))
+        }
+        pub fn lanes(self) -> [f32; 4] { self.0 }
+    }
+
+    impl Mask {
+        /// bit `i` for lane `i`
+        pub fn bits(self) -> u8 {
+            (0..4).fold(0, |b, i| b | ((self.0[i] as u8) << i))
+        }
+    }
+}
+
+use lanes::F32x4;
+
+/// Four complex numbers, one per lane.
+#[derive(Clone, Copy)]
+struct Cx4 { re: F32x4, im: F32x4 }
+
+impl Cx4 {
+    fn new(v: [Cx; 4]) -> Cx4 {
+        Cx4 {
+            re: F32x4::new(v.map(|c| c.re as f32)),
+            im: F32x4::new(v.map(|c| c.im as f32)),
+        }
+    }
+
+    fn splat(a: &Cx) -> Cx4 {
+        Cx4 { re: F32x4::splat(a.re as f32), im: F32x4::splat(a.im as f32) }
+    }
+
+    fn add(&self, o: &Cx4) -> Cx4 {
+        Cx4 { re: self.re.add(o.re), im: self.im.add(o.im) }
+    }
+
+    // Same operations in the same order as `Cx::mul()`.
+    fn mul(&self, o: &Cx4) -> Cx4 {
+        Cx4 {
+            re: self.re.mul(o.re).sub(self.im.mul(o.im)),
+            im: self.im.mul(o.re).add(self.re.mul(o.im)),
+        }
+    }
+
+    fn sqmod(&self) -> F32x4 { self.re.mul(self.re).add(self.im.mul(self.im)) }
+
+    fn lanes(&self) -> [Cx; 4] {
+        let (re, im) = (self.re.lanes(), self.im.lanes());
+        core::array::from_fn(|i| Cx { re: re[i] as f64, im: im[i] as f64 })
+    }
+}
+
+/// Where each lane's orbit is, and the smallest squared modulus it's had.
+struct Orbits { cur: Cx4, min_sqmod: F32x4 }
+
+impl Orbits {
+    fn new(start: &[OrbitState; 4]) -> Orbits {
+        Orbits {
+            cur: Cx4::new(start.map(|s| s.z)),
+            min_sqmod: F32x4::new(start.map(|s| s.min_sqmod as f32)),
+        }
+    }
+
+    /**
+    Everything both iterators do after each step: track the smallest squared
+    modulus, and record the results of lanes that have just diverged.
+    Returns the bits of the lanes that are done.
+    */
+    #[inline(always)]
+    fn step_done(
+        &mut self,
+        n: u16,
+        done: u8,
+        res: &mut [IterResult; 4],
+        limits: &Limits,
+        ln_degree: f64,
+    ) -> u8 {
+        let sqmod = self.cur.sqmod();
+        self.min_sqmod = self.min_sqmod.select(sqmod.lt(self.min_sqmod), sqmod);
+        let escaped = sqmod.gt(F32x4::splat(limits.sq_mod_limit as f32)).bits() & !done;
+        if escaped != 0 {
+            let (cur, min_sqmod) = (self.cur.lanes(), self.min_sqmod.lanes());
+            for i in 0..4 {
+                if escaped & (1 << i) != 0 {
+                    res[i] = IterResult::escaped(
+                        n, &cur[i], min_sqmod[i] as f64,
+                        limits.sq_mod_limit, ln_degree, limits.axis
+                    );
+                }
+            }
+        }
+        done | escaped
+    }
+
+    /// The results for the lanes that never diverged.
+    fn finish(&self, done: u8, res: &mut [IterResult; 4], limits: &Limits) {
+        let (cur, min_sqmod) = (self.cur.lanes(), self.min_sqmod.lanes());
+        for i in 0..4 {
+            if done & (1 << i) == 0 {
+                res[i] = IterResult::captive(
+                    limits.iter_limit, limits.metric, min_sqmod[i] as f64, &cur[i]
+                );
+            }
+        }
+    }
+}
+
+/**
+`mandelbrot_iter_from()` for the four points `c` at once, in single
+precision, picking up from the four `start`s after `n0` iterations.
+*/
+pub fn mandelbrot_iter4(
+    c: [Cx; 4],
+    n0: u16, start: &[OrbitState; 4],
+    limits: &Limits,
+) -> [IterResult; 4] {
+    let c = Cx4::new(c);
+    let mut o = Orbits::new(start);
+    let mut res = [IterResult::escaped_plain(0); 4];
+    let mut done = 0u8;
+
+    for n in n0..limits.iter_limit {
+        o.cur = c.add(&o.cur.mul(&o.cur));
+        done = o.step_done(n, done, &mut res, limits, core::f64::consts::LN_2);
+        if done == 0b1111 { return res; }
+    }
+    o.finish(done, &mut res, limits);
+    res
+}
+
+/// `polynomial_iter_from()` for four orbits at once, in single precision.
+pub fn polynomial_iter4(
+    n0: u16, start: &[OrbitState; 4],
+    poly: &Poly,
+    limits: &Limits,
+) -> [IterResult; 4] {
+    let mut cx4 = [Cx4::splat(&Cx { re: 0.0, im: 0.0 }); MAX_COEFFS];
+    for (v, c) in cx4.iter_mut().zip(poly.coeffs.iter()) { *v = Cx4::splat(c); }
+    let degree = poly.degree;
+    let mut o = Orbits::new(start);
+    let mut res = [IterResult::escaped_plain(0); 4];
+    let mut done = 0u8;
+
+    for n in n0..limits.iter_limit {
+        let mut new = Cx4::splat(&Cx { re: 0.0, im: 0.0 });
+        let mut z   = Cx4::splat(&Cx { re: 1.0, im: 0.0 });
+        for c in &cx4[..degree] {
+            let t = z.mul(c);
+            new = new.add(&t);
+            z = z.mul(&o.cur);
+        }
+        let t = z.mul(&cx4[degree]);
+        o.cur = new.add(&t);
+        done = o.step_done(n, done, &mut res, limits, poly.ln_degree);
+        if done == 0b1111 { return res; }
+    }
+    o.finish(done, &mut res, limits);
+    res
+}
+
+/// One complex number in single precision, with `Cx4`'s operations.
+#[derive(Clone, Copy)]
+struct Cx1 { re: f32, im: f32 }
+
+impl Cx1 {
+    fn new(v: &Cx) -> Cx1 { Cx1 { re: v.re as f32, im: v.im as f32 } }
+
+    fn add(&self, o: &Cx1) -> Cx1 { Cx1 { re: self.re + o.re, im: self.im + o.im } }
+
+    fn mul(&self, o: &Cx1) -> Cx1 {
+        Cx1 {
+            re: self.re * o.re - self.im * o.im,
+            im: self.im * o.re + self.re * o.im,
+        }
+    }
+
+    fn sqmod(&self) -> f32 { self.re * self.re + self.im * self.im }
+
+    fn wide(&self) -> Cx { Cx { re: self.re as f64, im: self.im as f64 } }
+}
+
+/**
+Iterate `step` on a lone orbit from `start` (after `n0` iterations) until
+it diverges, in single precision, the way `Orbits` does for each lane.
+*/
+#[inline(always)]
+fn iter1<F: Fn(&Cx1) -> Cx1>(
+    n0: u16, start: &OrbitState,
+    step: F,
+    limits: &Limits,
+    ln_degree: f64,
+) -> IterResult {
+    let mut cur = Cx1::new(&start.z);
+    let mut min_sqmod = start.min_sqmod as f32;
+    let sq_mod_limit = limits.sq_mod_limit as f32;
+
+    for n in n0..limits.iter_limit {
+        cur = step(&cur);
+        let sqmod = cur.sqmod();
+        if sqmod < min_sqmod { min_sqmod = sqmod; }
+        if sqmod > sq_mod_limit {
+            return IterResult::escaped(
+                n, &cur.wide(), min_sqmod as f64,
+                limits.sq_mod_limit, ln_degree, limits.axis
+            );
+        }
+    }
+    IterResult::captive(limits.iter_limit, limits.metric, min_sqmod as f64, &cur.wide())
+}
+
+/// `mandelbrot_iter4()` for just the one point `c`.
+pub fn mandelbrot_iter(c: &Cx, n0: u16, start: &OrbitState, limits: &Limits) -> IterResult {
+    let c = Cx1::new(c);
+    iter1(n0, start, |z| c.add(&z.mul(z)), limits, core::f64::consts::LN_2)
+}
+
+/// `polynomial_iter4()` for just the one orbit.
+pub fn polynomial_iter(
+    n0: u16, start: &OrbitState,
+    poly: &Poly,
+    limits: &Limits,
+) -> IterResult {
+    let coeffs = poly.coeffs.map(|c| Cx1::new(&c));
+    let degree = poly.degree;
+    let step = |cur: &Cx1| {
+        let mut new = Cx1 { re: 0.0, im: 0.0 };
+        let mut z   = Cx1 { re: 1.0, im: 0.0 };
+        for c in &coeffs[..degree] {
+            new = new.add(&z.mul(c));
+            z = z.mul(cur);
+        }
+        new.add(&z.mul(&coeffs[degree]))
+    };
+    iter1(n0, start, step, limits, poly.ln_degree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::same_result;
+    use crate::{InteriorMetric, TextureAxis};
+
+    /// A lone point comes out exactly as it would in any lane.
+    #[test]
+    fn one_matches_four() {
+        let limits = Limits {
+            sq_mod_limit: 1_000_000.0, iter_limit: 300,
+            metric: InteriorMetric::MinModulus, axis: TextureAxis::Angle,
+        };
+        let c = [
+            Cx { re: -0.75, im: 0.1 }, Cx { re: 0.3, im: 0.5 },
+            Cx { re: -0.1, im: 0.2 }, Cx { re: 0.26, im: -0.003 },
+        ];
+        let zero = OrbitState::start(Cx { re: 0.0, im: 0.0 });
+        let four = mandelbrot_iter4(c, 0, &[zero; 4], &limits);
+        for (c, res) in c.iter().zip(four.iter()) {
+            assert!(same_result(&mandelbrot_iter(c, 0, &zero, &limits), res));
+        }
+
+        let mut coeffs = [Cx { re: 0.0, im: 0.0 }; crate::MAX_COEFFS];
+        coeffs[0] = Cx { re: -0.1, im: 0.65 };
+        coeffs[2] = Cx { re: 1.0, im: 0.0 };
+        let poly = Poly::new(&coeffs, 3).unwrap();
+        let start = c.map(OrbitState::start);
+        let four = polynomial_iter4(0, &start, &poly, &limits);
+        for (start, res) in start.iter().zip(four.iter()) {
+            assert!(same_result(&polynomial_iter(0, start, &poly, &limits), res));
+        }
+    }
+}
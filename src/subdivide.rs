@@ -0,0 +1,192 @@
+/*!
+Mariani–Silver subdivision: iterating only the edges of uniform regions.
+
+Take a rectangle of the image and iterate the pixels around its edge. If
+they all came out the same, assume everything inside does, too, and fill
+it in without iterating it. If not, cut the rectangle in two across its
+longer side (iterating the cut), and try again with each half. Big areas
+inside the set, or far enough out that they're all one band, cost only
+their perimeters.
+
+The assumption is exactly right for the Mandelbrot set as a shape (it and
+the regions around it bounded by each iteration count are all connected,
+with no holes), but the image only _samples_ the plane, so a feature thin
+enough to slip between the pixels of a rectangle's edge can get painted
+over. In practice that means the occasional missing speck or filament a
+pixel or two wide. Julia sets of the polynomial iterator can be
+disconnected, so they lose specks a bit more readily. Either way, even
+views full of filaments have no more than one pixel in five thousand whose
+iteration count differs from iterating every pixel, and views without any
+usually have none.
+
+Regions only get filled when filling them loses nothing else: points that
+diverged only if the texture isn't being used (its data are different for
+every pixel), and points that didn't only if the caller says the interior
+values don't matter (see `iterate()`).
+*/
+
+use crate::{IterResult, OrbitState, PixelIter, Pixels, TextureAxis};
+
+/// Rectangles with fewer rows or columns inside than this just get iterated.
+const MIN_INSIDE: usize = 3;
+
+/// The buffers for a band of rows, and what's allowed to be filled in.
+struct Band<'a> {
+    buff: &'a mut [u16],
+    interior: &'a mut [u16],
+    orbit: &'a mut [u32],
+    state: &'a mut [OrbitState],
+    /// first row of the band
+    top: usize,
+    xpix: usize,
+    fill_escaped: bool,
+    fill_captive: bool,
+    limit: u16,
+}
+
+impl Band<'_> {
+    fn set(&mut self, xp: usize, yp: usize, r: IterResult) {
+        let idx = (yp - self.top) * self.xpix + xp;
+        self.buff[idx] = r.n;
+        self.interior[idx] = r.interior;
+        self.orbit[idx] = r.orbit;
+        self.state[idx] = r.state;
+    }
+
+    fn get(&self, xp: usize, yp: usize) -> IterResult {
+        let idx = (yp - self.top) * self.xpix + xp;
+        IterResult {
+            n: self.buff[idx], interior: self.interior[idx],
+            orbit: self.orbit[idx], state: self.state[idx],
+        }
+    }
+}
+
+/**
+Fill `px`, which holds the image's `rows` (and starts at the first of
+them), using subdivision. `captive_fill_ok` says
+whether the `INTERIOR` values of points that didn't diverge can be made up.
+*/
+pub fn fill_rows(
+    it: &PixelIter,
+    rows: core::ops::Range<usize>,
+    px: Pixels,
+    captive_fill_ok: bool,
+) {
+    let Pixels { itermap: buff, interior, orbit, state } = px;
+    let xpix = it.dp.xpix;
+    if rows.is_empty() || xpix == 0 { return; }
+    let mut band = Band {
+        buff, interior, orbit, state,
+        top: rows.start,
+        xpix,
+        fill_escaped: it.dp.texture_axis == TextureAxis::Off,
+        fill_captive: captive_fill_ok,
+        limit: it.limits.iter_limit,
+    };
+    let (x0, x1, y0, y1) = (0, xpix - 1, rows.start, rows.end - 1);
+    for xp in x0..=x1 {
+        band.set(xp, y0, it.at(xp, y0));
+        if y1 > y0 { band.set(xp, y1, it.at(xp, y1)); }
+    }
+    for yp in (y0 + 1)..y1 {
+        band.set(x0, yp, it.at(x0, yp));
+        if x1 > x0 { band.set(x1, yp, it.at(x1, yp)); }
+    }
+    subdivide(it, &mut band, x0, y0, x1, y1);
+}
+
+/**
+Deal with the inside of the rectangle with corners (`x0`, `y0`) and
+(`x1`, `y1`), whose edges have already been iterated.
+*/
+fn subdivide(
+    it: &PixelIter,
+    band: &mut Band,
+    x0: usize, y0: usize,
+    x1: usize, y1: usize,
+) {
+    if x1 - x0 < 2 || y1 - y0 < 2 { return; }
+
+    if x1 - x0 <= MIN_INSIDE || y1 - y0 <= MIN_INSIDE {
+        for yp in (y0 + 1)..y1 {
+            for xp in (x0 + 1)..x1 { band.set(xp, yp, it.at(xp, yp)); }
+        }
+        return;
+    }
+
+    if let Some(r) = uniform_edge(band, x0, y0, x1, y1) {
+        let fill = if r.n < band.limit { band.fill_escaped } else { band.fill_captive };
+        if fill {
+            for yp in (y0 + 1)..y1 {
+                for xp in (x0 + 1)..x1 { band.set(xp, yp, r); }
+            }
+            return;
+        }
+    }
+
+    if x1 - x0 >= y1 - y0 {
+        let xm = x0 + (x1 - x0) / 2;
+        for yp in (y0 + 1)..y1 { band.set(xm, yp, it.at(xm, yp)); }
+        subdivide(it, band, x0, y0, xm, y1);
+        subdivide(it, band, xm, y0, x1, y1);
+    } else {
+        let ym = y0 + (y1 - y0) / 2;
+        for xp in (x0 + 1)..x1 { band.set(xp, ym, it.at(xp, ym)); }
+        subdivide(it, band, x0, y0, x1, ym);
+        subdivide(it, band, x0, ym, x1, y1);
+    }
+}
+
+/**
+If every pixel on the edge of the rectangle has the same iteration count,
+the first one's result; otherwise `None`.
+*/
+fn uniform_edge(
+    band: &Band,
+    x0: usize, y0: usize,
+    x1: usize, y1: usize,
+) -> Option<IterResult> {
+    let first = band.get(x0, y0);
+    let same = |xp: usize, yp: usize| band.get(xp, yp).n == first.n;
+    for xp in x0..=x1 {
+        if !same(xp, y0) || !same(xp, y1) { return None; }
+    }
+    for yp in y0..=y1 {
+        if !same(x0, yp) || !same(x1, yp) { return None; }
+    }
+    Some(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{brute_force_and, lock};
+    use crate::{load_preset, set_coeff, set_n_coeffs, update_color_map};
+
+    #[test]
+    fn close_to_brute_force() {
+        const XPIX: usize = 300;
+        const YPIX: usize = 200;
+        let _guard = lock();
+        unsafe {
+            load_preset(6, false);
+            update_color_map();
+            for (c, use_poly, view) in [
+                ((0.0, 0.0), false, (-2.2, 2.05, 3.0)),
+                ((0.0, 0.0), false, (-0.8, 0.2, 0.1)),
+                ((0.0, 0.0), false, (-0.75, 0.12, 0.01)),
+                ((-0.1, 0.65), true, (-0.3, 0.3, 0.4)),
+                ((-0.7269, 0.1889), true, (-0.3, 0.3, 0.4)),
+                ((-0.8, 0.156), true, (-0.3, 0.3, 0.4)),
+            ] {
+                set_coeff(0, c.0, c.1);
+                set_coeff(1, 0.0, 0.0);
+                set_coeff(2, 1.0, 0.0);
+                set_n_coeffs(3);
+                let [brute, sub] = brute_force_and(1, XPIX, YPIX, view, use_poly);
+                let differ = brute.iter().zip(&sub).filter(|(a, b)| a != b).count();
+                assert!(differ * 5000 <= XPIX * YPIX, "{} differ for {:?}", differ, view);
+            }
+        }
+    }
+}
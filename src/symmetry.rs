@@ -0,0 +1,229 @@
+/*!
+Iterating half of a symmetric image and mirroring the rest.
+
+The Mandelbrot iterator is symmetric about the real axis: the orbit of the
+conjugate of a point is the conjugate of its orbit, so the two diverge (or
+don't) together. So is the polynomial iterator when all the coefficients
+are real. When the polynomial is odd or even (only odd or only even powers
+of `z` have nonzero coefficients), its Julia set is symmetric through the
+origin instead: the orbit of `-z` is the negation of the orbit of `z`, or
+(from the first iteration on) the same orbit.
+
+When the view straddles the line (or point) of symmetry, the pixels on one
+side of it that have a mirror image on the other side get marked as `kept`
+(see the `reuse` module), and once the rest have been iterated, they get
+copied over from their mirror images. With the real axis across the middle
+of the image, as in the web UI's home view, that's nearly half the pixels.
+
+A pixel and its mirror image are only exactly opposite each other up to
+rounding, as with reused pixels, and the axis has to fall on a row of
+pixels (or halfway between two) to within `reuse::SLACK` of a pixel.
+Rotated, skewed, or warped views aren't mirrored, and neither are tiles;
+the other strategies skip most of the iterating already, so they don't
+bother either.
+*/
+
+use crate::reuse::{Kept, SLACK};
+use crate::{Cx, DrawParams, IteratorType, OrbitState, Strategy, TextureAxis, Warp};
+
+/// How a point's orbit relates to its mirror image's.
+#[derive(Clone, Copy)]
+enum Flip {
+    /// conjugated (mirrored in the real axis)
+    Conj,
+    /// negated (mirrored through the origin)
+    Negate,
+    /// the same
+    Keep,
+}
+
+impl Flip {
+    fn z(self, z: Cx) -> Cx {
+        match self {
+            Flip::Conj   => Cx { re: z.re, im: -z.im },
+            Flip::Negate => Cx { re: -z.re, im: -z.im },
+            Flip::Keep   => z,
+        }
+    }
+
+    /// The mirror image's `ORBIT` data; only the angle changes.
+    fn orbit(self, orbit: u32, axis: TextureAxis) -> u32 {
+        if axis != TextureAxis::Angle || orbit == 0 { return orbit; }
+        let angle = (orbit >> 16) as u16;
+        let angle = match self {
+            Flip::Conj   => angle.wrapping_neg(),
+            Flip::Negate => angle.wrapping_add(0x8000),
+            Flip::Keep   => angle,
+        };
+        (orbit & 0xffff) | ((angle as u32) << 16)
+    }
+}
+
+/**
+Which pixels of the image mirror which. Pixel (`xp`, `yp`) in the
+rectangle of columns `x0..x1` and rows `y0..y1` is the mirror image of
+(`col_sum - xp`, `row_sum - yp`), or of (`xp`, `row_sum - yp`) if there's
+no `col_sum` (mirroring in the real axis).
+*/
+#[derive(Clone, Copy)]
+pub struct Mirror {
+    row_sum: usize,
+    col_sum: Option<usize>,
+    flip: Flip,
+    x0: usize, x1: usize,
+    y0: usize, y1: usize,
+}
+
+impl Mirror {
+    /// The pixels that get copied rather than iterated.
+    pub fn kept(&self) -> Kept { Kept::rect(self.x0, self.x1, self.y0, self.y1) }
+
+    /**
+    Copy the data in `buff`, `interior`, `orbit`, and `state` (for an
+    `xpix`-wide image with texture axis `axis`) into the mirrored pixels.
+    */
+    pub fn fill(
+        &self,
+        xpix: usize,
+        axis: TextureAxis,
+        buff: &mut [u16],
+        interior: &mut [u16],
+        orbit: &mut [u32],
+        state: &mut [OrbitState],
+    ) {
+        let flip = self.flip;
+        for yp in self.y0..self.y1 {
+            let sy = self.row_sum - yp;
+            for xp in self.x0..self.x1 {
+                let sx = match self.col_sum { Some(t) => t - xp, None => xp };
+                let (dst, src) = (yp * xpix + xp, sy * xpix + sx);
+                buff[dst] = buff[src];
+                interior[dst] = interior[src];
+                orbit[dst] = flip.orbit(orbit[src], axis);
+                state[dst] = OrbitState {
+                    z: flip.z(state[src].z),
+                    min_sqmod: state[src].min_sqmod,
+                };
+            }
+        }
+    }
+}
+
+/**
+`2 * k` rounded to the nearest whole number, if it's within `SLACK` of
+one and no bigger than `limit`.
+*/
+fn doubled(k: f64, limit: usize) -> Option<usize> {
+    let r = crate::fmath::floor(2.0 * k + 0.5);
+    if (2.0 * k - r).abs() > SLACK || r < 0.0 || r > limit as f64 { None }
+    else { Some(r as usize) }
+}
+
+/**
+How the image `dp` describes (iterated with the polynomial `coeffs`, of
+which there are `n_coeffs`) can be mirrored, if it can.
+*/
+pub fn find(dp: &DrawParams, coeffs: &[Cx], n_coeffs: usize) -> Option<Mirror> {
+    let whole = dp.full_xpix == dp.xpix && dp.full_ypix == dp.ypix
+        && dp.x_off == 0 && dp.y_off == 0
+        && dp.apron_x == 0 && dp.apron_y == 0;
+    if !whole || dp.strategy != Strategy::BruteForce
+        || dp.matrix != crate::IDENTITY || dp.warp != Warp::Identity
+    { return None; }
+
+    // (about the real axis, through the origin)
+    let (conj, point) = match dp.iterator {
+        IteratorType::Mandelbrot => (true, None),
+        IteratorType::Polynomial => {
+            if n_coeffs == 0 { return None; }
+            let used = &coeffs[..n_coeffs.min(coeffs.len())];
+            let zero = |parity: usize| used.iter().enumerate()
+                .all(|(k, c)| k % 2 != parity || (c.re == 0.0 && c.im == 0.0));
+            let point = if zero(1) { Some(Flip::Keep) }
+                else if zero(0) { Some(Flip::Negate) }
+                else { None };
+            (used.iter().all(|c| c.im == 0.0), point)
+        },
+    };
+    let flip = if conj { Flip::Conj } else { point? };
+
+    let (xpix, ypix) = (dp.xpix, dp.ypix);
+    let height = dp.width * ypix as f64 / xpix as f64;
+    let (pw, ph) = (dp.width / xpix as f64, height / ypix as f64);
+    let row_sum = doubled(dp.y / ph + dp.y_lo / ph, 2 * ypix)?;
+    let (y0, y1) = (row_sum / 2 + 1, ypix.min(row_sum + 1));
+    let (col_sum, x0, x1) = if conj { (None, 0, xpix) } else {
+        let t = doubled(-dp.x / pw - dp.x_lo / pw, 2 * xpix)?;
+        (Some(t), (t + 1).saturating_sub(xpix), xpix.min(t + 1))
+    };
+    if y0 >= y1 || x0 >= x1 { return None; }
+    Some(Mirror { row_sum, col_sum, flip, x0, x1, y0, y1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mandelbrot_iter, InteriorMetric, DEFAULT_DRAW_PARAMS};
+
+    const XPIX: usize = 24;
+    const YPIX: usize = 16;
+
+    /**
+    Every pixel of a small view with the real axis across it, iterated
+    directly, has to match what mirroring the pixels above the axis gives.
+    The pixels are powers of two on the plane, so mirror images are exactly
+    conjugates.
+    */
+    #[test]
+    fn conjugates_match() {
+        for (axis, metric) in [
+            (TextureAxis::Angle, InteriorMetric::MinModulus),
+            (TextureAxis::Trap, InteriorMetric::FinalModulus),
+        ] {
+            let dp = DrawParams {
+                xpix: XPIX, ypix: YPIX, full_xpix: XPIX, full_ypix: YPIX,
+                x: -2.0, y: 1.0, width: 3.0,
+                texture_axis: axis, interior_metric: metric,
+                ..DEFAULT_DRAW_PARAMS
+            };
+            let mirror = find(&dp, &dp.coeffs, dp.n_coeffs).unwrap();
+            let kept = mirror.kept();
+            assert!(kept.count() > 0);
+
+            let limits = dp.limits(200);
+            let mut buff = [0u16; XPIX * YPIX];
+            let mut interior = [0u16; XPIX * YPIX];
+            let mut orbit = [0u32; XPIX * YPIX];
+            let mut state = [OrbitState::start(Cx { re: 9.0, im: 9.0 }); XPIX * YPIX];
+            let mut direct = [None; XPIX * YPIX];
+            for yp in 0..YPIX {
+                for xp in 0..XPIX {
+                    let c = dp.point_at(xp, yp);
+                    let res = mandelbrot_iter(c.re, c.im, &limits);
+                    let idx = yp * XPIX + xp;
+                    direct[idx] = Some(res);
+                    if kept.has(xp, yp) { continue; }
+                    buff[idx] = res.n;
+                    interior[idx] = res.interior;
+                    orbit[idx] = res.orbit;
+                    state[idx] = res.state;
+                }
+            }
+            mirror.fill(XPIX, axis, &mut buff, &mut interior, &mut orbit, &mut state);
+
+            for (idx, res) in direct.iter().enumerate() {
+                let res = res.unwrap();
+                let (xp, yp) = (idx % XPIX, idx / XPIX);
+                assert!(buff[idx] == res.n && interior[idx] == res.interior,
+                        "({}, {}) iterations", xp, yp);
+                assert!(orbit[idx] == res.orbit, "({}, {}) orbit", xp, yp);
+                // Only orbits that didn't diverge are kept.
+                if res.n == limits.iter_limit {
+                    assert!(state[idx].z == res.state.z
+                            && state[idx].min_sqmod == res.state.min_sqmod,
+                            "({}, {}) state", xp, yp);
+                }
+            }
+        }
+    }
+}
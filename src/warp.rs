@@ -0,0 +1,70 @@
+/*!
+Non-linear maps of the complex plane ("warps") for distorted views.
+
+After a pixel has been placed on the plane (see `DrawParams::point_at()`),
+the point it lands on goes through the current `Warp` before it's handed to
+the iterator. So the image is a plain, undistorted picture of the _warped_
+plane: with `Inversion`, for instance, whatever's way out toward infinity
+shows up around the inversion point, and vice versa.
+*/
+
+use crate::{Cx, fmath};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Warp {
+    /// no warp at all; the plane as it is
+    Identity,
+    /// `p + 1/(z - p)`: inversion about the point `p`
+    Inversion(Cx),
+    /// `(az + b)/(cz + d)`, with the coefficients in that order
+    Mobius([Cx; 4]),
+    /**
+    `p + (z - p)(1 + k(|z - p|/r)²)`, a lens centered on `p` with radius `r`
+    and strength `k`. Positive strengths bulge the middle out like a fisheye
+    (or barrel distortion); negative ones pinch it in (pincushion).
+    */
+    Fisheye { center: Cx, radius: f64, strength: f64 },
+    /**
+    Polar coordinates about `p`, unrolled: the real part of `z` is taken as
+    an angle (in radians) and the imaginary part as a distance from `p`. A
+    view 2π wide then shows the whole way around `p`, with the distance
+    growing toward the top of the image.
+    */
+    Polar(Cx),
+    /**
+    Like `Polar`, but the imaginary part of `z` is the natural log of the
+    distance from `p`, for exponential maps (see the `expmap` module).
+    */
+    LogPolar(Cx),
+}
+
+impl Warp {
+    /// Where the point `z` of the image goes on the plane.
+    pub fn apply(&self, z: Cx) -> Cx {
+        match self {
+            Warp::Identity => z,
+            Warp::Inversion(p) => p.add(&z.sub(p).recip()),
+            Warp::Mobius([a, b, c, d]) => {
+                let num = a.mul(&z).add(b);
+                let den = c.mul(&z).add(d);
+                num.mul(&den.recip())
+            },
+            Warp::Fisheye { center, radius, strength } => {
+                let dz = z.sub(center);
+                let scale = 1.0 + strength * dz.sqmod() / (radius * radius);
+                Cx { re: center.re + dz.re * scale, im: center.im + dz.im * scale }
+            },
+            Warp::Polar(p) => Cx {
+                re: p.re + z.im * fmath::cos(z.re),
+                im: p.im + z.im * fmath::sin(z.re),
+            },
+            Warp::LogPolar(p) => {
+                let r = fmath::exp(z.im);
+                Cx {
+                    re: p.re + r * fmath::cos(z.re),
+                    im: p.im + r * fmath::sin(z.re),
+                }
+            },
+        }
+    }
+}
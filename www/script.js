@@ -76,8 +76,8 @@ digits of a checksum of the buffer contents.
 function checksum_buffer() {
     const arr = new Uint8ClampedArray(
         jswmod.exports.memory.buffer,
-        jswmod.exports.BUFFER.value,
-        4 * 1920 * 1080
+        jswmod.exports.image_ptr(),
+        4 * current_params.x_pixels * current_params.y_pixels
     );
     let csum = 0;
     for (let n of arr) { csum = csum + n; }
@@ -92,7 +92,9 @@ const DEFAULT_PARAMS = {
     x_pixels: 1200,     // image width in pixels
     y_pixels: 800,      // image height in pixels
     x: -2.0,            // real coordinate of upper-left-hand corner
+    x_lo: 0.0,          //   plus this much, for when x alone isn't enough
     y: 1.0,             // imaginary coordinate of upper-left-hand corner
+    y_lo: 0.0,          //   plus this much
     width: 3.0,         // width of image on the Complex Plane
     zoom: 2.0,          // default zoom factor
     smooth: 0.0,        // default smoothing amount
@@ -107,7 +109,25 @@ to the image parameters here. These are used by several functions, like
 */
 let current_params = DEFAULT_PARAMS;
 
-/* Update the CANVAS with the current data in the wasm module's IMAGE buffer. */
+/*
+Move the coordinate `hi + lo` by `d`, keeping the digits that don't fit in
+`hi` in `lo`. The module takes the corner this way (as a "double-double"),
+because after zooming in far enough, a whole pixel is smaller than the gap
+between one double and the next.
+*/
+function add_dd(hi, lo, d) {
+    const s = hi + d;
+    const dd = s - hi;
+    const err = (hi - (s - dd)) + (d - dd) + lo;
+    const new_hi = s + err;
+    return [new_hi, err - (new_hi - s)];
+}
+
+/*
+Update the CANVAS with the current data in the wasm module's IMAGE buffer.
+The module's memory gets replaced whenever the image gets bigger, so this
+has to wrap the current one every time.
+*/
 function update_canvas(xpix, ypix) {
     CANVAS.width  = xpix;
     CANVAS.height = ypix;
@@ -115,7 +135,7 @@ function update_canvas(xpix, ypix) {
     const img_data = new ImageData(
         new Uint8ClampedArray(
             jswmod.exports.memory.buffer,
-            jswmod.exports.IMAGE.value,
+            jswmod.exports.image_ptr(),
             4 * xpix * ypix
         ),
         xpix
@@ -127,26 +147,50 @@ function update_canvas(xpix, ypix) {
 Ask the wasm module to re-iterate and recolor the image with the current
 image parameters. Takes an argument with the same structure as the
 `DEFAULT_PARAMS` constant, above.
+
+The image is drawn progressively: a blocky version shows up right away and
+gets refined a pass at a time. The work is done in slices of about
+`PIXELS_PER_SLICE` pixels, with the browser getting a chance to draw (and
+the user a chance to click) in between. Starting another render abandons
+this one.
 */
+const PIXELS_PER_SLICE = 20000;
+let render_generation = 0;
 function render_image(params) {
     STATUS.show("Drawing...");
     //console.log(params);
     //console.log(` pre cksum: ${checksum_buffer()}`);
 
-    jswmod.exports.redraw(
+    jswmod.exports.render_cancel();
+    const generation = ++render_generation;
+    const ok = jswmod.exports.render_begin_dd(
         params.x_pixels,
         params.y_pixels,
-        params.x,
-        params.y,
+        params.x, params.x_lo,
+        params.y, params.y_lo,
         params.width,
         (params.iter.type == "polynomial"),
         params.smooth,
+        true,       // progressive
     );
+    if (!ok) {
+        STATUS.show("Image too large.");
+        return;
+    }
     
-    //console.log(`post cksum: ${checksum_buffer()}`);
-    
-    update_canvas(params.x_pixels, params.y_pixels);
-    STATUS.hide();
+    function next_slice() {
+        if (generation != render_generation) { return; }
+        if (jswmod.exports.render_step(PIXELS_PER_SLICE)) {
+            update_canvas(params.x_pixels, params.y_pixels);
+        }
+        if (jswmod.exports.render_done()) {
+            //console.log(`post cksum: ${checksum_buffer()}`);
+            STATUS.hide();
+        } else {
+            setTimeout(next_slice, 0);
+        }
+    }
+    next_slice();
 }
 
 function recolor() {
@@ -155,6 +199,12 @@ function recolor() {
     //console.log(params);
     //console.log(` pre cksum: ${checksum_buffer()}`);
     COLOR.update_map();
+    // The wasm module won't recolor an image it's still drawing, so start
+    // it over with the new colors instead.
+    if (!jswmod.exports.render_done()) {
+        render_image(params);
+        return;
+    }
     //console.log(`post cksum: ${checksum_buffer()}`);
     update_canvas(params.x_pixels, params.y_pixels);
     STATUS.hide();
@@ -175,6 +225,8 @@ async function init() {
     )
     .then(function(obj) {
         jswmod = obj.instance;
+        COLOR.list_presets();
+        COLOR.load_preset(0);
         jswmod.exports.update_color_map();
         STATUS.hide();
         COLOR.update_map();
@@ -187,8 +239,8 @@ async function init() {
 
 /*
 Called when the canvas is clicked, this function returns an object containing
-the pixel coordinates of the click and whether the shift or control keys
-were down.
+the coordinates of the click (relative to the upper-left-hand corner) and
+whether the shift or control keys were down.
 */
 function click_details(evt) {
     const p = current_params;
@@ -197,12 +249,9 @@ function click_details(evt) {
     const yfrac = (evt.y - crect.top) / crect.height;
     const height = p.width * p.y_pixels / p.x_pixels;
     
-    const newx = p.x + (xfrac * p.width);
-    const newy = p.y - (yfrac * height);
-    
     return {
-        x: newx,
-        y: newy,
+        dx: xfrac * p.width,
+        dy: -yfrac * height,
         shift: evt.shiftKey,
         ctrl:  evt.ctrlKey,
     };
@@ -225,14 +274,22 @@ function new_params(click) {
     
     const new_width = p.width / zoom_factor;
     const new_height = height / zoom_factor;
-    const newx = click.x - (new_width / 2);
-    const newy = click.y + (new_height / 2);
+    const dx = click.dx - (new_width / 2);
+    const dy = click.dy + (new_height / 2);
+    // Move by whole pixels (of the smaller of the old and new pixels), so
+    // the module can reuse as much of the image as it can.
+    const xstep = Math.min(p.width, new_width) / p.x_pixels;
+    const ystep = Math.min(height, new_height) / p.y_pixels;
+    const [newx, newx_lo] = add_dd(p.x, p.x_lo, Math.round(dx / xstep) * xstep);
+    const [newy, newy_lo] = add_dd(p.y, p.y_lo, Math.round(dy / ystep) * ystep);
     
     const np = {
         x_pixels: p.x_pixels,
         y_pixels: p.y_pixels,
         x: newx,
+        x_lo: newx_lo,
         y: newy,
+        y_lo: newy_lo,
         width: new_width,
         zoom: p.zoom,
         smooth: p.smooth,
@@ -256,24 +313,28 @@ hack of zoom in/out buttons that fire this function.
 function mobile_zoom(zoom_in) {
     const p = current_params;
     const height = p.width * p.y_pixels / p.x_pixels;
-    let new_x, new_y, new_width;
+    let dx, dy, new_width;
     if (zoom_in) {
         const frac = (1 - (1/p.zoom)) / 2;
-        new_x = p.x + frac * p.width;
-        new_y = p.y - frac * height;
+        dx = frac * p.width;
+        dy = -frac * height;
         new_width = p.width / p.zoom;
     } else {
         const frac = (p.zoom - 1) / 2;
-        new_x = p.x - frac * p.width;
-        new_y = p.y + frac * height;
+        dx = -frac * p.width;
+        dy = frac * height;
         new_width = p.width * p.zoom;
     }
+    const [new_x, new_x_lo] = add_dd(p.x, p.x_lo, dx);
+    const [new_y, new_y_lo] = add_dd(p.y, p.y_lo, dy);
     
     const new_params = {
         x_pixels: p.x_pixels,
         y_pixels: p.y_pixels,
         x: new_x,
+        x_lo: new_x_lo,
         y: new_y,
+        y_lo: new_y_lo,
         width: new_width,
         zoom: p.zoom,
         smooth: p.smooth,
@@ -310,11 +371,8 @@ const COLOR = {
     tbody:   document.querySelector("div#color-map table tbody"),
     add:     document.getElementById("add-color"),
     default: document.getElementById("idcolor"),
+    preset:  document.getElementById("preset"),
     hexre:   /[0-9a-fA-F][0-9a-fA-F]/g,
-    defaults: [
-        ["#000000", 128, "#ffffff"],
-        ["#ffffff", 256, "#000000"],
-    ],
 };
 COLOR.to_rgb = function(s) {
     return Array.from(s.matchAll(COLOR.hexre), m => parseInt(m, 16));
@@ -438,11 +496,41 @@ function add_gradient(start, steps, end) {
     COLOR.tbody.appendChild(tr);
 }
 
-// Set initial color parameter defaults.
-for (const tup of COLOR.defaults) {
-    add_gradient(tup[0], tup[1], tup[2]);
-}
-COLOR.current_params = COLOR.get_params();
+/*
+The built-in palettes live in the wasm module; these fill the preset menu
+from it, and replace the gradients in the table with one of them.
+*/
+COLOR.list_presets = function() {
+    const bytes = jswmod.exports.memory.buffer;
+    const decoder = new TextDecoder();
+    recursive_clear(COLOR.preset);
+    for (let n = 0; n < jswmod.exports.n_presets(); n++) {
+        const name = decoder.decode(new Uint8Array(
+            bytes,
+            jswmod.exports.preset_name(n),
+            jswmod.exports.preset_name_len(n)
+        ));
+        const opt = document.createElement("option");
+        opt.value = n;
+        opt.textContent = name;
+        COLOR.preset.appendChild(opt);
+    }
+};
+COLOR.load_preset = function(n) {
+    const to_hex = c => "#" + c.toString(16).padStart(6, "0");
+    const len = jswmod.exports.preset_len(n);
+    recursive_clear(COLOR.tbody);
+    for (let i = 0; i < len; i++) {
+        add_gradient(
+            to_hex(jswmod.exports.preset_color(n, i)),
+            jswmod.exports.preset_shades(n, i),
+            to_hex(jswmod.exports.preset_color(n, (i + 1) % len))
+        );
+    }
+};
+COLOR.preset.addEventListener("change", function(evt) {
+    COLOR.load_preset(Number(evt.target.value));
+});
 
 COLOR.add.onclick = function(evt) { 
     evt.preventDefault();